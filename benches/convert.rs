@@ -0,0 +1,55 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use jane_street_mock_interview::conversion::graph::UnitGraph;
+use jane_street_mock_interview::conversion::unit_conversion::ConversionQuery;
+use std::hint::black_box;
+
+// Pairs that take one fact, several facts, a prefix and an offset.
+const PAIRS: [(&str, &str); 4] = [("m", "ft"), ("mi", "in"), ("km", "in"), ("degF", "degC")];
+
+fn convert(c: &mut Criterion) {
+    let graph = UnitGraph::standard();
+    let compiled = graph.compile().expect("the standard facts compile");
+    let ids: Vec<_> = PAIRS
+        .iter()
+        .map(|&(from, to)| (compiled.id(from).unwrap(), compiled.id(to).unwrap()))
+        .collect();
+
+    let mut group = c.benchmark_group("convert");
+    group.bench_function("ConversionQuery::convert_units", |b| {
+        b.iter(|| {
+            for &(from, to) in &PAIRS {
+                black_box(ConversionQuery::convert(black_box(2.), from, to).unwrap());
+            }
+        })
+    });
+    group.bench_function("UnitGraph::convert", |b| {
+        b.iter(|| {
+            for &(from, to) in &PAIRS {
+                black_box(graph.convert(black_box(2.), from, to).unwrap());
+            }
+        })
+    });
+    group.bench_function("CompiledGraph::convert by name", |b| {
+        b.iter(|| {
+            for &(from, to) in &PAIRS {
+                let (from, to) = (compiled.id(from).unwrap(), compiled.id(to).unwrap());
+                black_box(compiled.convert(black_box(2.), from, to).unwrap());
+            }
+        })
+    });
+    group.bench_function("CompiledGraph::convert by id", |b| {
+        b.iter(|| {
+            for &(from, to) in &ids {
+                black_box(compiled.convert(black_box(2.), from, to).unwrap());
+            }
+        })
+    });
+    group.finish();
+
+    c.bench_function("UnitGraph::compile", |b| {
+        b.iter(|| black_box(graph.compile().unwrap()))
+    });
+}
+
+criterion_group!(benches, convert);
+criterion_main!(benches);
@@ -1,9 +1,111 @@
-use clap::{Arg, ArgAction, ArgMatches, Command};
-pub fn parse_args() -> (i64, Option<String>, u8, Option<(f32, String, String)>) {
-    let matches = Command::new("Dice Game Simulations & Unit Conversion")
+use chrono::NaiveDate;
+use clap::error::ErrorKind;
+use clap::{Arg, ArgAction, Command};
+use jane_street_mock_interview::conversion::format::{Format, Notation, Precision, UnitStyle};
+use jane_street_mock_interview::conversion::number::Number;
+use jane_street_mock_interview::conversion::route::RoutePolicy;
+use jane_street_mock_interview::conversion::uncertainty;
+
+/// The value `-u` converts: a number, optionally measured, followed by its
+/// unit, or a mixed quantity such as `5 ft 11 in` that carries its own units.
+pub enum Amount {
+    Measured {
+        value: Number,
+        uncertainty: f64,
+        unit: String,
+    },
+    Mixed(String),
+}
+
+pub struct Args {
+    pub num_sims: i64,
+    pub output: Option<String>,
+    pub verbosity: u8,
+    /// The value and the unit to convert it to, which may be left out for
+    /// `best` or `breakdown`.
+    pub unit_conversion: Option<(Amount, Option<String>)>,
+    /// Convert to the most readable unit of this system instead.
+    pub best: Option<String>,
+    /// Split the value across these units instead, e.g. `ft,in`.
+    pub breakdown: Option<Vec<String>>,
+    pub facts: Option<String>,
+    pub rates: Option<String>,
+    pub as_of: Option<NaiveDate>,
+    pub tolerance: Option<f64>,
+    pub strict: bool,
+    pub exact: bool,
+    pub explain: bool,
+    pub route: RoutePolicy,
+    pub format: Format,
+    pub query: Option<String>,
+    pub queries: Option<String>,
+    pub repl: bool,
+    /// The CSV to convert, the system to convert it to, and where to write
+    /// the result (stdout if `None`).
+    pub convert_table: Option<(String, String, Option<String>)>,
+    /// The format to export the conversion graph in (`mermaid`, `dot`,
+    /// `json` or `toml`) and where to write it (stdout if `None`).
+    pub export_graph: Option<(String, Option<String>)>,
+    /// The local port to serve the JSON API on.
+    pub serve: Option<u16>,
+}
+
+pub fn parse_args() -> Args {
+    let mut command = Command::new("Dice Game Simulations & Unit Conversion")
         .author("Jackson Brim")
         .version("0.1.0")
         .about("Dice Game Simulation & Unit Conversion")
+        .subcommand(Command::new("repl").about("Convert interactively, keeping the facts loaded"))
+        .subcommand(
+            Command::new("convert-table")
+                .about("Convert the value and unit columns of a CSV to one system of units")
+                .arg(
+                    Arg::new("input")
+                        .required(true)
+                        .help("A CSV whose header names a `value` and a `unit` column"),
+                )
+                .arg(
+                    Arg::new("system")
+                        .long("system")
+                        .short('s')
+                        .required(true)
+                        .help("The system to convert to, e.g. si, imperial or us"),
+                )
+                .arg(
+                    Arg::new("out")
+                        .long("out")
+                        .help("Where to write the converted CSV (default stdout)"),
+                ),
+        )
+        .subcommand(
+            Command::new("export-graph")
+                .about("Write the units and facts as a diagram or a JSON or TOML registry")
+                .arg(
+                    Arg::new("format")
+                        .long("format")
+                        .short('f')
+                        .value_parser(["mermaid", "dot", "json", "toml"])
+                        .default_value("mermaid")
+                        .help("A Mermaid class diagram, a Graphviz digraph, or a registry that --facts reads back"),
+                )
+                .arg(
+                    Arg::new("out")
+                        .long("out")
+                        .help("Where to write it (default stdout)"),
+                ),
+        )
+        .subcommand(
+            Command::new("serve")
+                .about("Serve conversions as a JSON API over HTTP on localhost")
+                .arg(
+                    Arg::new("port")
+                        .long("port")
+                        .short('p')
+                        .value_parser(clap::value_parser!(u16))
+                        .default_value("8080")
+                        .help("The port to listen on, 0 for any free one"),
+                ),
+        )
         .arg(
             Arg::new("num-sims")
                 .short('n')
@@ -23,32 +125,148 @@ pub fn parse_args() -> (i64, Option<String>, u8, Option<(f32, String, String)>)
                 .short('u')
                 .long("unit-conversion")
                 .action(ArgAction::SetTrue)
-                .help("Perform a unit conversion, requires -v <value> -f <from> -t <to>"),
+                .help("Perform a unit conversion: -u <value> <from> <to>, or -u <mixed value> <to>"),
+        )
+        .arg(
+            Arg::new("facts")
+                .long("facts")
+                .global(true)
+                .action(ArgAction::Set)
+                .help("A facts file with one `lhs = factor rhs` per line to convert with"),
+        )
+        .arg(
+            Arg::new("rates")
+                .long("rates")
+                .action(ArgAction::Set)
+                .global(true)
+                .help("A CSV of dated rates (from,to,rate,effective,until), e.g. exchange rates"),
+        )
+        .arg(
+            Arg::new("as-of")
+                .long("as-of")
+                .value_parser(|s: &str| NaiveDate::parse_from_str(s, "%Y-%m-%d"))
+                .global(true)
+                .help("Convert with the rates in effect on this date, YYYY-MM-DD (default today)"),
+        )
+        .arg(
+            Arg::new("tolerance")
+                .long("tolerance")
+                .global(true)
+                .value_parser(clap::value_parser!(f64))
+                .help("The relative disagreement allowed around a cycle of facts (default 1e-4)"),
+        )
+        .arg(
+            Arg::new("strict")
+                .long("strict")
+                .global(true)
+                .action(ArgAction::SetTrue)
+                .help("Refuse to load inconsistent facts instead of warning about them"),
+        )
+        .arg(
+            Arg::new("exact")
+                .long("exact")
+                .global(true)
+                .action(ArgAction::SetTrue)
+                .help("Convert with exact ratios, only rounding the final answer"),
+        )
+        .arg(
+            Arg::new("explain")
+                .long("explain")
+                .global(true)
+                .action(ArgAction::SetTrue)
+                .help("Show each step of a conversion and the facts it used"),
+        )
+        .arg(
+            Arg::new("route")
+                .long("route")
+                .value_parser(["hops", "exact", "uncertainty"])
+                .default_value("hops")
+                .global(true)
+                .help("Where several chains of facts connect two units, take the one with the fewest facts, the fewest measured facts, or the least uncertainty"),
+        )
+        .arg(
+            Arg::new("decimals")
+                .long("decimals")
+                .value_parser(clap::value_parser!(usize))
+                .global(true)
+                .help("Write results with this many decimal places"),
+        )
+        .arg(
+            Arg::new("sig-figs")
+                .long("sig-figs")
+                .value_parser(clap::value_parser!(usize))
+                .conflicts_with("decimals")
+                .global(true)
+                .help("Write results with this many significant figures"),
+        )
+        .arg(
+            Arg::new("notation")
+                .long("notation")
+                .value_parser(["plain", "sci", "eng"])
+                .default_value("plain")
+                .global(true)
+                .help("Write results in plain, scientific or engineering notation"),
+        )
+        .arg(
+            Arg::new("separators")
+                .long("separators")
+                .action(ArgAction::SetTrue)
+                .global(true)
+                .help("Group the digits of results in thousands, e.g. 1,234,567"),
+        )
+        .arg(
+            Arg::new("unit-style")
+                .long("unit-style")
+                .value_parser(["name", "symbol"])
+                .default_value("name")
+                .global(true)
+                .help("Write the unit of a -u result by its full name or its symbol"),
+        )
+        .arg(
+            Arg::new("best")
+                .long("best")
+                .requires("unit-conversion")
+                .conflicts_with_all(["to", "breakdown"])
+                .help("Convert -u to the most readable unit of a system, e.g. metric or imperial"),
+        )
+        .arg(
+            Arg::new("breakdown")
+                .long("breakdown")
+                .value_delimiter(',')
+                .requires("unit-conversion")
+                .conflicts_with("to")
+                .help("Split -u across several units, largest first, e.g. ft,in or hr,min,s"),
+        )
+        .arg(
+            Arg::new("query")
+                .short('q')
+                .long("query")
+                .action(ArgAction::Set)
+                .help("Answer a README-style query, e.g. -q '2 m = ? in'"),
+        )
+        .arg(
+            Arg::new("queries")
+                .long("queries")
+                .action(ArgAction::Set)
+                .help("Answer every query in a file, one answer per line"),
         )
         .arg(
             Arg::new("value")
                 .index(1)
-                .value_parser(clap::value_parser!(f32))
                 .requires("unit-conversion")
-                .help("The value to convert"),
+                .help("The value to convert, optionally measured, e.g. '2±0.01', or a mixed quantity with its units, e.g. '5 ft 11 in' or 1h30m"),
         )
         .arg(
             Arg::new("from")
                 .index(2)
-                .value_parser(clap::builder::ValueParser::new(
-                    clap::builder::PossibleValuesParser::new(["m", "in", "ft", "hr", "s"]),
-                ))
                 .requires("unit-conversion")
-                .help("The unit to convert from ('m', 'in', 'ft', 'hr', 's')"),
+                .help("The unit to convert from: any unit the facts know, e.g. 'm' or 'km/hr' (the unit to convert to after a mixed value)"),
         )
         .arg(
             Arg::new("to")
                 .index(3)
-                .value_parser(clap::builder::ValueParser::new(
-                    clap::builder::PossibleValuesParser::new(["m", "in", "ft", "hr", "s"]),
-                ))
                 .requires("unit-conversion")
-                .help("The unit to convert to ('m', 'in', 'ft', 'hr', 's')"),
+                .help("The unit to convert to: any unit the facts know, e.g. 'in' or 'm/sec' (not needed with --best or --breakdown)"),
         )
         .arg(
             Arg::new("verbose")
@@ -62,21 +280,146 @@ pub fn parse_args() -> (i64, Option<String>, u8, Option<(f32, String, String)>)
                 -vv => DEBUG,
                 -vvv => TRACE",
                 ),
-        )
-        .get_matches();
+        );
+    let matches = command.get_matches_mut();
 
     let num_sims = *matches.get_one::<i64>("num-sims").unwrap_or(&0);
     let output = matches.get_one::<String>("output").cloned();
     let verbosity = *matches.get_one::<u8>("verbose").unwrap_or(&0);
     let unit_conversion_match = *matches.get_one::<bool>("unit-conversion").unwrap_or(&false);
     let unit_conversion = if unit_conversion_match {
-        let value = *matches.get_one::<f32>("value").unwrap();
-        let from = matches.get_one::<String>("from").cloned().unwrap();
-        let to = matches.get_one::<String>("to").cloned().unwrap();
-        Some((value, from, to))
+        let value = matches
+            .get_one::<String>("value")
+            .cloned()
+            .unwrap_or_default();
+        let mut units = ["from", "to"]
+            .into_iter()
+            .filter_map(|id| matches.get_one::<String>(id).cloned());
+        let amount = match uncertainty::parse_measured(&value) {
+            Ok((value, uncertainty)) => Amount::Measured {
+                value,
+                uncertainty,
+                unit: units.next().unwrap_or_else(|| {
+                    command
+                        .error(
+                            ErrorKind::MissingRequiredArgument,
+                            "missing the unit to convert from",
+                        )
+                        .exit()
+                }),
+            },
+            // A number that doesn't parse as one, e.g. for its exponent, isn't
+            // a mixed value either.
+            Err(e)
+                if uncertainty::split_uncertainty(&value).is_some()
+                    || value.parse::<f64>().is_ok() =>
+            {
+                command.error(ErrorKind::ValueValidation, e).exit()
+            }
+            Err(_) => Amount::Mixed(value),
+        };
+        let to = units.next();
+        if units.next().is_some() {
+            command
+                .error(
+                    ErrorKind::TooManyValues,
+                    "a mixed value is followed by the unit to convert to only",
+                )
+                .exit()
+        }
+        Some((amount, to))
     } else {
         None
     };
+    let best = matches.get_one::<String>("best").cloned();
+    let breakdown = matches
+        .get_many::<String>("breakdown")
+        .map(|units| units.cloned().collect());
+    // After a mixed value the unit to convert to is the second positional,
+    // which clap doesn't know conflicts with these.
+    let has_target = unit_conversion.as_ref().is_some_and(|(_, to)| to.is_some());
+    if has_target && (best.is_some() || breakdown.is_some()) {
+        command
+            .error(
+                ErrorKind::ArgumentConflict,
+                "--best and --breakdown take the place of the unit to convert to",
+            )
+            .exit()
+    }
+    let facts = matches.get_one::<String>("facts").cloned();
+    let rates = matches.get_one::<String>("rates").cloned();
+    let as_of = matches.get_one::<NaiveDate>("as-of").copied();
+    let tolerance = matches.get_one::<f64>("tolerance").copied();
+    let strict = matches.get_flag("strict");
+    let exact = matches.get_flag("exact");
+    let explain = matches.get_flag("explain");
+    let route = matches
+        .get_one::<String>("route")
+        .and_then(|name| RoutePolicy::from_name(name))
+        .unwrap_or_default();
+    let precision = match (
+        matches.get_one::<usize>("decimals"),
+        matches.get_one::<usize>("sig-figs"),
+    ) {
+        (Some(&places), _) => Precision::Decimals(places),
+        (_, Some(&figures)) => Precision::Significant(figures),
+        _ => Precision::Shortest,
+    };
+    let notation = match matches.get_one::<String>("notation").map(String::as_str) {
+        Some("sci") => Notation::Scientific,
+        Some("eng") => Notation::Engineering,
+        _ => Notation::Plain,
+    };
+    let unit_style = match matches.get_one::<String>("unit-style").map(String::as_str) {
+        Some("symbol") => UnitStyle::Symbol,
+        _ => UnitStyle::Name,
+    };
+    let format = Format {
+        precision,
+        notation,
+        separator: matches.get_flag("separators").then_some(','),
+        unit_style,
+    };
+    let query = matches.get_one::<String>("query").cloned();
+    let queries = matches.get_one::<String>("queries").cloned();
+    let repl = matches.subcommand_name() == Some("repl");
+    let convert_table = matches.subcommand_matches("convert-table").map(|table| {
+        let arg = |id: &str| table.get_one::<String>(id).cloned();
+        (
+            arg("input").unwrap_or_default(),
+            arg("system").unwrap_or_default(),
+            arg("out"),
+        )
+    });
+    let export_graph = matches.subcommand_matches("export-graph").map(|export| {
+        let arg = |id: &str| export.get_one::<String>(id).cloned();
+        (arg("format").unwrap_or_default(), arg("out"))
+    });
+    let serve = matches
+        .subcommand_matches("serve")
+        .and_then(|serve| serve.get_one::<u16>("port").copied());
 
-    (num_sims, output, verbosity, unit_conversion)
+    Args {
+        num_sims,
+        output,
+        verbosity,
+        unit_conversion,
+        best,
+        breakdown,
+        facts,
+        rates,
+        as_of,
+        tolerance,
+        strict,
+        exact,
+        explain,
+        route,
+        format,
+        query,
+        queries,
+        repl,
+        convert_table,
+        export_graph,
+        serve,
+    }
 }
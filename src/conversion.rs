@@ -1,180 +1,380 @@
+pub mod compiled;
+pub mod consistency;
+pub mod diagram;
+pub mod dimension;
+pub mod explain;
+pub mod expression;
+pub mod facts;
+pub mod format;
+pub mod graph;
+pub mod mixed;
+pub mod number;
+pub mod prefix;
+pub mod quantity;
+pub mod query;
+pub mod rates;
+pub mod registry;
+pub mod route;
+pub mod server;
+pub mod session;
+pub mod table;
+pub mod typed;
+pub mod uncertainty;
+
 pub mod unit_conversion {
+    use super::consistency::Inconsistency;
+    use super::dimension::Dimension;
+    use super::explain::Explanation;
+    use super::expression::UnitExpr;
+    use super::facts::Fact;
+    use super::graph::UnitGraph;
+    use super::number::Number;
+    use super::route::RoutePolicy;
+    use super::uncertainty::Uncertain;
+    use num_rational::BigRational;
     use std::fmt;
+    use std::sync::OnceLock;
 
-    #[derive(Debug)]
-    pub struct ConversionError(pub String);
+    /// Everything that can go wrong reading facts or converting with them.
+    /// Each variant carries the names and numbers involved so callers can
+    /// react without parsing the message `Display` writes.
+    #[derive(Debug, Clone, PartialEq)]
+    pub enum ConversionError {
+        /// A unit name the graph doesn't know, with the known names closest to
+        /// it.
+        UnknownUnit {
+            name: String,
+            suggestions: Vec<String>,
+        },
+        /// Two unit expressions that measure different things, like `mi/hr`
+        /// and `m`.
+        IncompatibleDimensions {
+            from: String,
+            to: String,
+            from_dimension: Dimension,
+            to_dimension: Dimension,
+        },
+        /// Two units of the same dimension that no chain of facts connects,
+        /// which only happens for absolute temperatures.
+        NoPath { from: String, to: String },
+        /// A name with several prefixed readings, e.g. `milli-in` or
+        /// `micro-n`, written as `prefix-unit`.
+        AmbiguousUnit {
+            name: String,
+            candidates: Vec<String>,
+        },
+        /// A unit that also reads as a prefixed unit, like `min` once `in`
+        /// takes SI prefixes, and wasn't declared to settle which it is.
+        PrefixCollision {
+            name: String,
+            prefix: String,
+            unit: String,
+        },
+        /// Input that doesn't parse. `position` is the byte offset in `input`
+        /// where parsing stopped.
+        ParseError {
+            input: String,
+            position: usize,
+            reason: String,
+        },
+        /// A line of a facts file that doesn't parse.
+        InvalidFact {
+            path: Option<String>,
+            line: usize,
+            text: String,
+            reason: String,
+        },
+        /// A facts file that can't be read.
+        ReadFacts { path: String, reason: String },
+        /// A registry written as JSON or TOML that doesn't describe units and
+        /// facts.
+        InvalidRegistry {
+            path: Option<String>,
+            reason: String,
+        },
+        /// Cycles of facts whose ratios disagree, refused in strict mode.
+        InconsistentFacts { cycles: Vec<Inconsistency> },
+        /// A symbol, alias or full name given to two different units.
+        DuplicateName {
+            name: String,
+            first: String,
+            second: String,
+        },
+        /// Two units connected by facts that each set their own dimension.
+        ConflictingDimensions { first: String, second: String },
+        /// A compound fact whose right-hand side leads back to its left-hand
+        /// side.
+        CircularDefinition { fact: Box<Fact> },
+        /// An absolute temperature with an offset, like `degC`, used inside a
+        /// compound unit such as `degC/s`, where only a difference makes sense.
+        AffineInCompound { unit: String, expression: String },
+        /// A conversion between an absolute temperature and a temperature
+        /// difference, like `degC` to `ΔdegF`.
+        AbsoluteAndDifference {
+            absolute: String,
+            difference: String,
+        },
+        /// A system of units no unit belongs to and no `system` line declares.
+        UnknownSystem { name: String, known: Vec<String> },
+        /// No unit declared in `system` measures `dimension`.
+        NoUnitInSystem {
+            system: String,
+            dimension: Dimension,
+        },
+    }
 
     impl fmt::Display for ConversionError {
         fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-            write!(f, "{}", self.0)
+            match self {
+                ConversionError::UnknownUnit { name, suggestions } => {
+                    write!(f, "Unknown unit `{}`", name)?;
+                    if !suggestions.is_empty() {
+                        let quoted: Vec<String> =
+                            suggestions.iter().map(|s| format!("`{}`", s)).collect();
+                        write!(f, ", did you mean {}?", quoted.join(" or "))?;
+                    }
+                    Ok(())
+                }
+                ConversionError::IncompatibleDimensions {
+                    from,
+                    to,
+                    from_dimension,
+                    to_dimension,
+                } => write!(
+                    f,
+                    "Cannot convert {} to {}: incompatible dimensions {} vs {}",
+                    from, to, from_dimension, to_dimension
+                ),
+                ConversionError::NoPath { from, to } => {
+                    write!(f, "No chain of facts connects {} and {}", from, to)
+                }
+                ConversionError::AmbiguousUnit { name, candidates } => {
+                    let quoted: Vec<String> = candidates
+                        .iter()
+                        .map(|candidate| match candidate.split_once('-') {
+                            Some((prefix, unit)) => format!("{}-`{}`", prefix, unit),
+                            None => format!("`{}`", candidate),
+                        })
+                        .collect();
+                    write!(
+                        f,
+                        "Ambiguous unit `{}`: it could be {}",
+                        name,
+                        quoted.join(" or ")
+                    )
+                }
+                ConversionError::PrefixCollision { name, prefix, unit } => write!(
+                    f,
+                    "Ambiguous unit `{}`: it could be its own unit or {}-`{}`. \
+                     Declare it with `unit {}` to keep it as its own unit",
+                    name, prefix, unit, name
+                ),
+                ConversionError::ParseError {
+                    input,
+                    position,
+                    reason,
+                } => write!(
+                    f,
+                    "Cannot parse `{}` at position {}: {}",
+                    input, position, reason
+                ),
+                ConversionError::InvalidFact {
+                    path,
+                    line,
+                    text,
+                    reason,
+                } => {
+                    if let Some(path) = path {
+                        write!(f, "{}: ", path)?;
+                    }
+                    write!(f, "line {}: {} in `{}`", line, reason, text)
+                }
+                ConversionError::ReadFacts { path, reason } => {
+                    write!(f, "Could not read facts file {}: {}", path, reason)
+                }
+                ConversionError::InvalidRegistry { path, reason } => {
+                    if let Some(path) = path {
+                        write!(f, "{}: ", path)?;
+                    }
+                    write!(f, "invalid registry: {}", reason)
+                }
+                ConversionError::InconsistentFacts { cycles } => {
+                    let cycles: Vec<String> = cycles.iter().map(|c| c.to_string()).collect();
+                    write!(f, "Inconsistent facts:\n  {}", cycles.join("\n  "))
+                }
+                ConversionError::DuplicateName {
+                    name,
+                    first,
+                    second,
+                } => write!(
+                    f,
+                    "`{}` can't name both `{}` and `{}`",
+                    name, first, second
+                ),
+                ConversionError::ConflictingDimensions { first, second } => write!(
+                    f,
+                    "`{}` and `{}` are connected by facts but both set their dimension",
+                    first, second
+                ),
+                ConversionError::CircularDefinition { fact } => {
+                    write!(f, "`{}` is defined in terms of itself", fact)
+                }
+                ConversionError::AffineInCompound { unit, expression } => write!(
+                    f,
+                    "Cannot use the absolute temperature `{}` in `{}`, use `Δ{}` for a temperature difference",
+                    unit, expression, unit
+                ),
+                ConversionError::AbsoluteAndDifference {
+                    absolute,
+                    difference,
+                } => write!(
+                    f,
+                    "Cannot convert between the absolute temperature `{}` and the temperature difference `{}`",
+                    absolute, difference
+                ),
+                ConversionError::UnknownSystem { name, known } => write!(
+                    f,
+                    "Unknown unit system `{}`, expected one of: {}",
+                    name,
+                    known.join(", ")
+                ),
+                ConversionError::NoUnitInSystem { system, dimension } => write!(
+                    f,
+                    "No unit in the `{}` system measures {}",
+                    system, dimension
+                ),
+            }
         }
     }
 
     impl std::error::Error for ConversionError {}
 
-    #[derive(Debug)]
-    pub enum Distance {
-        Meters,
-        Feet,
-        Inches,
-    }
-
-    #[derive(Debug)]
-    pub enum Time {
-        Hours,
-        Minutes,
+    pub(crate) fn standard_graph() -> &'static UnitGraph {
+        static GRAPH: OnceLock<UnitGraph> = OnceLock::new();
+        GRAPH.get_or_init(UnitGraph::standard)
     }
 
     #[derive(Debug)]
-    pub enum ConversionUnit {
-        Distance(Distance),
-        Time(Time),
+    pub struct ConversionQuery<'a> {
+        graph: &'a UnitGraph,
+        from: UnitExpr,
+        to: UnitExpr,
+        value: Number,
+        uncertainty: f64,
+        route: RoutePolicy,
     }
 
-    // Implementations for Display traits ...
-    impl fmt::Display for Distance {
-        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-            match self {
-                Distance::Meters => write!(f, "meters",),
-                Distance::Feet => write!(f, "feet",),
-                Distance::Inches => write!(f, "inches",),
-            }
+    impl ConversionQuery<'static> {
+        pub fn new(n: impl Into<Number>, from: &str, to: &str) -> Result<Self, ConversionError> {
+            Self::with_graph(standard_graph(), n, from, to)
         }
-    }
 
-    impl fmt::Display for Time {
-        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-            match self {
-                Time::Hours => write!(f, "hours",),
-                Time::Minutes => write!(f, "minutes",),
-            }
+        pub fn convert(n: impl Into<Number>, from: &str, to: &str) -> Result<f64, ConversionError> {
+            let query = ConversionQuery::new(n, from, to)?;
+            query.convert_units()
         }
     }
 
-    impl fmt::Display for ConversionUnit {
-        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-            match self {
-                ConversionUnit::Distance(distance) => write!(f, "{}", distance),
-                ConversionUnit::Time(time) => write!(f, "{}", time),
+    impl<'a> ConversionQuery<'a> {
+        /// Builds a query against the facts in `graph` instead of the standard
+        /// facts. Either unit may be compound, e.g. `mi/hr` or `kg*m/sec^2`,
+        /// and use aliases or full names, which are rewritten to symbols.
+        pub fn with_graph(
+            graph: &'a UnitGraph,
+            n: impl Into<Number>,
+            from: &str,
+            to: &str,
+        ) -> Result<Self, ConversionError> {
+            let query = Self {
+                graph,
+                from: graph.parse_unit(from)?,
+                to: graph.parse_unit(to)?,
+                value: n.into(),
+                uncertainty: 0.,
+                route: RoutePolicy::default(),
+            };
+            let from_dimension = graph.dimension(&query.from)?;
+            let to_dimension = graph.dimension(&query.to)?;
+            if from_dimension != to_dimension {
+                return Err(ConversionError::IncompatibleDimensions {
+                    from: query.from.to_string(),
+                    to: query.to.to_string(),
+                    from_dimension,
+                    to_dimension,
+                });
             }
+            Ok(query)
         }
-    }
 
-    #[derive(Debug)]
-    pub struct ConversionQuery {
-        from: ConversionUnit,
-        to: ConversionUnit,
-        value: f32,
-    }
+        /// Gives the value a standard uncertainty, e.g. `0.01` for `2 ± 0.01 m`.
+        pub fn with_uncertainty(mut self, uncertainty: f64) -> Self {
+            self.uncertainty = uncertainty;
+            self
+        }
 
-    impl ConversionQuery {
-        fn new(n: f32, from: &str, to: &str) -> Result<Self, ConversionError> {
-            let from_unit = match from {
-                "m" => ConversionUnit::Distance(Distance::Meters),
-                "in" => ConversionUnit::Distance(Distance::Inches),
-                "ft" => ConversionUnit::Distance(Distance::Feet),
-                "hr" => ConversionUnit::Time(Time::Hours),
-                "min" => ConversionUnit::Time(Time::Minutes),
-                _ => return Err(ConversionError(format!("Unknown from type: {}", from))),
-            };
+        /// Chooses between several chains of facts connecting the units by
+        /// `route` instead of taking the one with the fewest facts.
+        pub fn with_route(mut self, route: RoutePolicy) -> Self {
+            self.route = route;
+            self
+        }
 
-            let to_unit = match to {
-                "m" => ConversionUnit::Distance(Distance::Meters),
-                "in" => ConversionUnit::Distance(Distance::Inches),
-                "ft" => ConversionUnit::Distance(Distance::Feet),
-                "hr" => ConversionUnit::Time(Time::Hours),
-                "min" => ConversionUnit::Time(Time::Minutes),
-                _ => return Err(ConversionError(format!("Unknown to type: {}", to))),
-            };
+        /// The unit converted from, written with symbols.
+        pub fn from(&self) -> &UnitExpr {
+            &self.from
+        }
 
-            match (&from_unit, &to_unit) {
-                (ConversionUnit::Time(_), ConversionUnit::Distance(_)) => {
-                    return Err(ConversionError(format!(
-                        "Cannot convert time to distance: unit: {}, from: {}, to: {}",
-                        n, from, to
-                    )));
-                }
-                (ConversionUnit::Distance(_), ConversionUnit::Time(_)) => {
-                    return Err(ConversionError(format!(
-                        "Cannot convert distance to time: unit: {}, from: {}, to: {}",
-                        n, from, to
-                    )));
-                }
-                _ => (),
-            }
+        /// The unit converted to, written with symbols.
+        pub fn to(&self) -> &UnitExpr {
+            &self.to
+        }
 
-            Ok(Self {
-                from: from_unit,
-                to: to_unit,
-                value: n,
-            })
+        pub fn convert_units(&self) -> Result<f64, ConversionError> {
+            self.graph
+                .convert_expr_by(self.value.value(), &self.from, &self.to, self.route)
         }
 
-        fn convert_units(&self) -> Result<f32, ConversionError> {
-            let res: Result<f32, ConversionError> = match (&self.from, &self.to) {
-                (ConversionUnit::Time(Time::Hours), ConversionUnit::Time(Time::Minutes)) => {
-                    Ok(self.value * 60.)
-                }
+        /// Converts with the exact ratios the facts were written with, e.g.
+        /// `2 m` is exactly `1968/25 in` given `m = 3.28 ft` and `ft = 12 in`.
+        pub fn convert_units_exact(&self) -> Result<BigRational, ConversionError> {
+            self.graph
+                .convert_expr_by(self.value.exact().clone(), &self.from, &self.to, self.route)
+        }
 
-                (ConversionUnit::Time(Time::Minutes), ConversionUnit::Time(Time::Hours)) => {
-                    Ok(self.value / 60.)
-                }
-                (
-                    ConversionUnit::Distance(Distance::Meters),
-                    ConversionUnit::Distance(Distance::Feet),
-                ) => Ok(self.value * 3.28),
-                (
-                    ConversionUnit::Distance(Distance::Meters),
-                    ConversionUnit::Distance(Distance::Inches),
-                ) => Ok(self.value * 3.28 * 12.),
-                (
-                    ConversionUnit::Distance(Distance::Feet),
-                    ConversionUnit::Distance(Distance::Inches),
-                ) => Ok(self.value * 12.),
-                (
-                    ConversionUnit::Distance(Distance::Feet),
-                    ConversionUnit::Distance(Distance::Meters),
-                ) => Ok(self.value / 3.28),
-                (
-                    ConversionUnit::Distance(Distance::Inches),
-                    ConversionUnit::Distance(Distance::Feet),
-                ) => Ok(self.value / 12.),
-                (
-                    ConversionUnit::Distance(Distance::Inches),
-                    ConversionUnit::Distance(Distance::Meters),
-                ) => Ok(self.value / 12. / 3.28),
-                _ => Err(self.get_conversion_error()),
-            };
-            Ok(res)?
+        /// Converts like [`ConversionQuery::convert_units`], propagating the
+        /// uncertainty of the value and of every measured fact used.
+        pub fn convert_uncertain(&self) -> Result<Uncertain, ConversionError> {
+            let value = Uncertain::new(self.value.value(), self.uncertainty);
+            self.graph
+                .convert_expr_by(value, &self.from, &self.to, self.route)
         }
 
-        fn get_conversion_error(&self) -> ConversionError {
-            match (&self.from, &self.to) {
-                (ConversionUnit::Time(time), ConversionUnit::Distance(_)) => {
-                    return ConversionError(format!(
-                        "Cannot convert time to distance: unit: {}, from: {}, to: {}",
-                        self.value, self.from, self.to
-                    ));
-                }
-                (ConversionUnit::Distance(distance), ConversionUnit::Time(_)) => {
-                    return ConversionError(format!(
-                        "Cannot convert distance to time: unit: {}, from: {}, to: {}",
-                        self.value, self.from, self.to
-                    ));
-                }
-                _ => {
-                    return ConversionError(format!(
-                        "Cannot convert for Unkonwn Conversion type: from: {:?}, to: {:?}",
-                        self.from, self.to
-                    ))
-                }
-            }
+        /// Converts like [`ConversionQuery::convert_units`], keeping each step,
+        /// the facts it used and the routes passed over.
+        pub fn explain(&self) -> Result<Explanation, ConversionError> {
+            self.graph
+                .explain_by(self.value.value(), &self.from, &self.to, self.route)
         }
 
-        pub fn convert(n: f32, from: &str, to: &str) -> Result<f32, ConversionError> {
-            let query = ConversionQuery::new(n, from, to)?;
+        pub fn convert_with(
+            graph: &'a UnitGraph,
+            n: impl Into<Number>,
+            from: &str,
+            to: &str,
+        ) -> Result<f64, ConversionError> {
+            let query = ConversionQuery::with_graph(graph, n, from, to)?;
             query.convert_units()
         }
+
+        pub fn convert_exact_with(
+            graph: &'a UnitGraph,
+            n: impl Into<Number>,
+            from: &str,
+            to: &str,
+        ) -> Result<BigRational, ConversionError> {
+            let query = ConversionQuery::with_graph(graph, n, from, to)?;
+            query.convert_units_exact()
+        }
     }
 }
 
@@ -185,21 +385,21 @@ mod tests {
     fn success1() {
         let input =
             ConversionQuery::new(2.0, "m", "in").expect("Expected creation of Conversion Query");
-        let res: f32 = input
+        let res: f64 = input
             .convert_units()
             .expect("Expected Unit Conversion 2 meters to inches");
-        let output: f32 = 78.72;
+        let output: f64 = 78.72;
         assert_eq!(res, output);
     }
     #[test]
     fn success2() {
         let input =
             ConversionQuery::new(13.0, "in", "m").expect("Expected creation of Conversion Query");
-        let res: f32 = input
+        let res: f64 = input
             .convert_units()
             .expect("Expected Unit Conversion 13.0 inches to meters");
-        let output: f32 = 0.330; // roughly
-        let precision: f32 = 1000.0; // For 3 decimal places
+        let output: f64 = 0.330; // roughly
+        let precision: f64 = 1000.0; // For 3 decimal places
                                      // Round both values to the same number of decimal places
         let res_rounded = (res * precision).round() / precision;
         let output_rounded = (output * precision).round() / precision;
@@ -207,16 +407,62 @@ mod tests {
         assert_eq!(res_rounded, output_rounded);
     }
     #[test]
+    fn exact_conversion() {
+        use num_rational::BigRational;
+        let input =
+            ConversionQuery::new(13.0, "in", "m").expect("Expected creation of Conversion Query");
+        let res = input
+            .convert_units_exact()
+            .expect("Expected exact Unit Conversion 13.0 inches to meters");
+        assert_eq!(res, BigRational::new(325.into(), 984.into()));
+        let res = ConversionQuery::new(2.0, "m", "in")
+            .and_then(|query| query.convert_units_exact())
+            .expect("Expected exact Unit Conversion 2 meters to inches");
+        assert_eq!(res, BigRational::new(1968.into(), 25.into()));
+    }
+    #[test]
+    fn unknown_unit_error() {
+        match ConversionQuery::new(1., "m", "furlong") {
+            Err(ConversionError::UnknownUnit { name, suggestions }) => {
+                assert_eq!(name, "furlong");
+                assert!(suggestions.is_empty());
+            }
+            other => panic!("Expected an unknown unit, got {:?}", other),
+        }
+    }
+    #[test]
+    fn unknown_unit_suggestions() {
+        match ConversionQuery::new(1., "hr", "mni") {
+            Err(ConversionError::UnknownUnit { suggestions, .. }) => {
+                assert_eq!(suggestions, vec!["mi".to_string(), "min".to_string()]);
+            }
+            other => panic!("Expected an unknown unit, got {:?}", other),
+        }
+    }
+    #[test]
+    fn aliases_and_full_names() {
+        let query = ConversionQuery::new(2., "meters", "inches")
+            .expect("Expected full names to be known units");
+        assert_eq!(
+            (query.from().to_string(), query.to().to_string()),
+            ("m".to_string(), "in".to_string())
+        );
+        assert_eq!(query.convert_units().unwrap(), 78.72);
+        let res = ConversionQuery::convert(1., "Kilometers/hour", "metre/s").unwrap();
+        assert!((res - 1. / 3.6).abs() < 1e-12);
+        assert_eq!(ConversionQuery::convert(1., "'", "\"").unwrap(), 12.);
+        assert_eq!(ConversionQuery::convert(1., "h", "minutes").unwrap(), 60.);
+        assert_eq!(ConversionQuery::convert(1., "Mm", "mm").unwrap(), 1e9);
+    }
+    #[test]
     fn conversion_query_input_error() {
         let result = ConversionQuery::new(13., "in", "hr");
         match result {
             Ok(_) => panic!("Expected an error, but got Ok"),
-            Err(e) => {
-                let expected_error = ConversionError(format!(
-                    "Cannot convert distance to time: unit: 13, from: in, to: hr"
-                ));
-                assert_eq!(e.to_string(), expected_error.to_string());
+            Err(ConversionError::IncompatibleDimensions { from, to, .. }) => {
+                assert_eq!((from.as_str(), to.as_str()), ("in", "hr"));
             }
+            Err(e) => panic!("Expected incompatible dimensions, got {:?}", e),
         }
     }
 }
@@ -0,0 +1,179 @@
+use super::dimension::Dimension;
+use std::collections::HashMap;
+
+/// A unit's index in a [`CompiledGraph`]. It is only meaningful for the
+/// table it came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct UnitId(u32);
+
+// One row of the table: `1 unit` is `scale` of its dimension's base unit,
+// plus `offset` base units for an absolute temperature.
+#[derive(Debug, Clone)]
+pub(crate) struct Entry {
+    pub(crate) symbol: String,
+    pub(crate) dimension: usize,
+    pub(crate) scale: f64,
+    pub(crate) offset: f64,
+    pub(crate) absolute: bool,
+    pub(crate) difference: bool,
+}
+
+/// A [`UnitGraph`](super::graph::UnitGraph) compiled by
+/// [`UnitGraph::compile`](super::graph::UnitGraph::compile) for converting
+/// the same units many times over. Every unit, every prefixed form of a unit
+/// that takes prefixes and every `Δ` form of an absolute temperature is one
+/// row of a dense table holding its ratio to the base unit of its dimension,
+/// so a conversion is two array reads and a multiply instead of a search
+/// through the facts.
+///
+/// Names are looked up once with [`CompiledGraph::id`]; conversions then go
+/// through the returned [`UnitId`]s. The table is a snapshot: facts added to
+/// the graph afterwards aren't in it. Compound units such as `m/s` aren't
+/// either, and since every ratio goes through the base unit the last digits
+/// can differ from [`UnitGraph::convert`](super::graph::UnitGraph::convert),
+/// which follows the facts between the two units.
+#[derive(Debug, Clone, Default)]
+pub struct CompiledGraph {
+    ids: HashMap<String, UnitId>,
+    // Lowercased names matched in any case, as the graph matches them.
+    folded: HashMap<String, UnitId>,
+    entries: Vec<Entry>,
+    dimensions: Vec<Dimension>,
+}
+
+impl CompiledGraph {
+    // The index of `dimension` among the table's dimensions, adding it if
+    // it's new.
+    pub(crate) fn intern(&mut self, dimension: Dimension) -> usize {
+        match self.dimensions.iter().position(|d| *d == dimension) {
+            Some(index) => index,
+            None => {
+                self.dimensions.push(dimension);
+                self.dimensions.len() - 1
+            }
+        }
+    }
+
+    // Adds a row, known by no name yet.
+    pub(crate) fn push(&mut self, entry: Entry) -> UnitId {
+        let id = UnitId(self.entries.len() as u32);
+        self.entries.push(entry);
+        id
+    }
+
+    // Makes `name` look up `id`, matched exactly or in any case.
+    pub(crate) fn name(&mut self, name: String, id: UnitId, any_case: bool) {
+        if any_case {
+            self.folded.entry(name.to_lowercase()).or_insert(id);
+        } else {
+            self.ids.entry(name).or_insert(id);
+        }
+    }
+
+    /// The ID of a unit written as the graph would read it: by symbol, alias,
+    /// full name or a prefixed form of one, e.g. `km` or `Kilometers`.
+    pub fn id(&self, name: &str) -> Option<UnitId> {
+        self.ids
+            .get(name)
+            .or_else(|| self.folded.get(&name.to_lowercase()))
+            .copied()
+    }
+
+    /// The symbol of a unit, with its prefix, e.g. `km`.
+    pub fn symbol(&self, id: UnitId) -> &str {
+        &self.entries[id.0 as usize].symbol
+    }
+
+    /// The dimension a unit measures.
+    pub fn dimension(&self, id: UnitId) -> &Dimension {
+        &self.dimensions[self.entries[id.0 as usize].dimension]
+    }
+
+    /// How many units, prefixed forms included, the table holds.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Converts `value` from one unit to another, or returns `None` if they
+    /// measure different dimensions or one is an absolute temperature and the
+    /// other a temperature difference.
+    pub fn convert(&self, value: f64, from: UnitId, to: UnitId) -> Option<f64> {
+        let from = &self.entries[from.0 as usize];
+        let to = &self.entries[to.0 as usize];
+        if from.dimension != to.dimension {
+            return None;
+        }
+        if (from.absolute || to.absolute) && (from.difference || to.difference) {
+            return None;
+        }
+        Some((value * from.scale + from.offset - to.offset) / to.scale)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::graph::UnitGraph;
+    use super::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() <= 1e-9 * a.abs().max(b.abs()).max(1.)
+    }
+
+    #[test]
+    fn agrees_with_the_graph_for_every_pair_of_units() {
+        let graph = UnitGraph::standard();
+        let compiled = graph.compile().unwrap();
+        let mut names: Vec<String> = graph.names();
+        names.extend(["km", "Kilometers", "ms", "µs", "us", "GiB", "kbit", "mL"].map(String::from));
+        for from in &names {
+            for to in &names {
+                let (Some(from_id), Some(to_id)) = (compiled.id(from), compiled.id(to)) else {
+                    panic!("Expected `{}` and `{}` in the table", from, to);
+                };
+                let expected = graph.convert(7.5, from, to);
+                let found = compiled.convert(7.5, from_id, to_id);
+                match (expected, found) {
+                    (Some(expected), Some(found)) => assert!(
+                        close(expected, found),
+                        "7.5 {} = {} {}, not {}",
+                        from,
+                        expected,
+                        to,
+                        found
+                    ),
+                    (expected, found) => {
+                        assert_eq!(expected.is_some(), found.is_some(), "{} -> {}", from, to)
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn looks_up_units_by_id() {
+        let graph = UnitGraph::standard();
+        let compiled = graph.compile().unwrap();
+        let (m, inch) = (compiled.id("m").unwrap(), compiled.id("inches").unwrap());
+        assert!(close(compiled.convert(2., m, inch).unwrap(), 78.72));
+        assert_eq!(compiled.symbol(inch), "in");
+        assert_eq!(compiled.id("KILOMETER"), compiled.id("km"));
+        assert_eq!(compiled.dimension(m), &Dimension::base("L"));
+        assert_eq!(compiled.id("fet"), None);
+
+        let (c, f) = (compiled.id("°C").unwrap(), compiled.id("degF").unwrap());
+        assert!(close(compiled.convert(100., c, f).unwrap(), 212.));
+        let delta = compiled.id("ΔdegC").unwrap();
+        assert!(close(
+            compiled
+                .convert(10., delta, compiled.id("K").unwrap())
+                .unwrap(),
+            10.
+        ));
+        assert_eq!(compiled.convert(10., delta, c), None);
+        assert_eq!(compiled.convert(1., m, compiled.id("s").unwrap()), None);
+    }
+}
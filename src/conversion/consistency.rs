@@ -0,0 +1,116 @@
+use super::facts::Fact;
+use super::graph::UnitGraph;
+use super::unit_conversion::ConversionError;
+use std::fmt;
+
+/// A cycle of facts whose ratios don't multiply back to 1, e.g. `m = 3.28 ft`,
+/// `ft = 12 in` and `m = 40 in`, or whose offsets don't add back to 0. Two
+/// compound facts defining the same unit differently are reported the same
+/// way, with the second as the closing fact.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Inconsistency {
+    /// The facts around the cycle. The last one is the fact that closes it.
+    pub facts: Vec<Fact>,
+    /// The factor of the closing fact implied by the rest of the cycle, or
+    /// NaN if the rest measures another dimension.
+    pub implied: f64,
+    /// The factor the closing fact actually states.
+    pub stated: f64,
+    /// The offset of the closing fact implied by the rest of the cycle.
+    pub implied_offset: f64,
+    /// The offset the closing fact actually states.
+    pub stated_offset: f64,
+    pub relative_error: f64,
+}
+
+impl fmt::Display for Inconsistency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let cycle: Vec<String> = self.facts.iter().map(|fact| fact.to_string()).collect();
+        let closing = self.facts.last().expect("a cycle has at least one fact");
+        if self.relative_error.is_infinite() {
+            return write!(
+                f,
+                "[{}] give {} two different dimensions",
+                cycle.join(", "),
+                closing.from
+            );
+        }
+        let offset = |offset: f64| match offset {
+            0. => String::new(),
+            _ if offset < 0. => format!(" - {}", -offset),
+            _ => format!(" + {}", offset),
+        };
+        write!(
+            f,
+            "[{}] implies 1 {} = {} {}{} but the facts state {}{} ({:.4}% apart)",
+            cycle.join(", "),
+            closing.from,
+            self.implied,
+            closing.to,
+            offset(self.implied_offset),
+            self.stated,
+            offset(self.stated_offset),
+            self.relative_error * 100.
+        )
+    }
+}
+
+/// How strictly to check facts for contradictions when they are loaded.
+#[derive(Debug, Clone, Copy)]
+pub struct ConsistencyCheck {
+    /// The largest relative disagreement around a cycle that is accepted.
+    pub tolerance: f64,
+    /// Refuse to load inconsistent facts instead of only warning about them.
+    pub strict: bool,
+}
+
+impl Default for ConsistencyCheck {
+    fn default() -> Self {
+        Self {
+            tolerance: 1e-4,
+            strict: false,
+        }
+    }
+}
+
+impl ConsistencyCheck {
+    /// Returns the inconsistent cycles in `graph` as warnings, or an error
+    /// listing all of them in strict mode.
+    pub fn verify(&self, graph: &UnitGraph) -> Result<Vec<Inconsistency>, ConversionError> {
+        let found = graph.inconsistencies(self.tolerance);
+        if self.strict && !found.is_empty() {
+            return Err(ConversionError::InconsistentFacts { cycles: found });
+        }
+        Ok(found)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strict_check_refuses_inconsistent_facts() {
+        let mut graph = UnitGraph::standard();
+        graph.add_fact(Fact::new("m", 40., "in"));
+
+        let lenient = ConsistencyCheck::default();
+        assert_eq!(
+            lenient
+                .verify(&graph)
+                .expect("Expected warnings only")
+                .len(),
+            1
+        );
+
+        let strict = ConsistencyCheck {
+            strict: true,
+            ..lenient
+        };
+        let err = strict.verify(&graph).unwrap_err();
+        assert!(matches!(&err, ConversionError::InconsistentFacts { cycles } if cycles.len() == 1));
+        assert!(err
+            .to_string()
+            .contains("[m = 3.28 ft, ft = 12 in, m = 40 in]"));
+    }
+}
@@ -0,0 +1,187 @@
+use super::expression::UnitExpr;
+use super::facts::{Fact, FactsFile};
+use std::collections::HashMap;
+use std::fmt::Write;
+
+// A unit, or the right-hand side of a compound fact such as `kg*m/s^2`.
+struct Node<'f> {
+    label: &'f str,
+    // The attributes of the unit's `unit` line, e.g. `dim=L`.
+    details: Vec<String>,
+    compound: bool,
+}
+
+// The nodes and edges of a diagram: every declared unit, then every unit or
+// compound unit a fact names, in the order they first appear, and one edge
+// per fact from its left-hand side to its right-hand side.
+struct Diagram<'f> {
+    nodes: Vec<Node<'f>>,
+    edges: Vec<(usize, usize, &'f Fact)>,
+}
+
+impl<'f> Diagram<'f> {
+    fn of(file: &'f FactsFile) -> Self {
+        let mut nodes: Vec<Node<'f>> = Vec::new();
+        let mut ids: HashMap<&str, usize> = HashMap::new();
+        let mut node = |label: &'f str, details: Vec<String>, compound: bool| {
+            *ids.entry(label).or_insert_with(|| {
+                nodes.push(Node {
+                    label,
+                    details,
+                    compound,
+                });
+                nodes.len() - 1
+            })
+        };
+        for decl in &file.units {
+            node(&decl.symbol, decl.attributes(), false);
+        }
+        let mut edges = Vec::new();
+        for fact in &file.facts {
+            let compound = fact
+                .to
+                .parse::<UnitExpr>()
+                .is_ok_and(|expr| expr.as_simple().is_none());
+            let from = node(&fact.from, Vec::new(), false);
+            let to = node(&fact.to, Vec::new(), compound);
+            edges.push((from, to, fact));
+        }
+        Self { nodes, edges }
+    }
+}
+
+/// Draws the registry as a Mermaid class diagram: a class per unit listing the
+/// attributes of its `unit` line, and an arrow per fact from its left-hand
+/// side to its right-hand side labeled with the factor, e.g. `m --> ft : 3.28`.
+/// The right-hand side of a compound fact is a class of its own marked
+/// `<<compound>>`.
+pub fn mermaid(file: &FactsFile) -> String {
+    let diagram = Diagram::of(file);
+    let mut out = String::from("---\ntitle: Unit Conversion Graph\n---\nclassDiagram\n");
+    for (id, node) in diagram.nodes.iter().enumerate() {
+        write!(out, "    class u{}[\"{}\"]", id, mermaid_text(node.label)).unwrap();
+        if node.details.is_empty() && !node.compound {
+            out.push('\n');
+            continue;
+        }
+        out.push_str(" {\n");
+        if node.compound {
+            out.push_str("        <<compound>>\n");
+        }
+        for detail in &node.details {
+            writeln!(out, "        {}", mermaid_text(detail)).unwrap();
+        }
+        out.push_str("    }\n");
+    }
+    for (from, to, fact) in &diagram.edges {
+        writeln!(out, "    u{} --> u{} : {}", from, to, factor(fact)).unwrap();
+    }
+    out
+}
+
+/// Draws the registry as a Graphviz DOT digraph, with the same nodes and
+/// edges as [`mermaid`]. Compound units are dashed boxes.
+pub fn dot(file: &FactsFile) -> String {
+    let diagram = Diagram::of(file);
+    let mut out = String::from("digraph units {\n    rankdir=LR;\n");
+    for (id, node) in diagram.nodes.iter().enumerate() {
+        let lines: Vec<String> = std::iter::once(node.label)
+            .chain(node.details.iter().map(String::as_str))
+            .map(dot_text)
+            .collect();
+        write!(out, "    u{} [label=\"{}\"", id, lines.join("\\n")).unwrap();
+        if node.compound {
+            out.push_str(", shape=box, style=dashed");
+        }
+        out.push_str("];\n");
+    }
+    for (from, to, fact) in &diagram.edges {
+        writeln!(
+            out,
+            "    u{} -> u{} [label=\"{}\"];",
+            from,
+            to,
+            dot_text(&factor(fact))
+        )
+        .unwrap();
+    }
+    out.push_str("}\n");
+    out
+}
+
+// What a fact multiplies by and adds, e.g. `3.28 ± 0.001` or `1 + 273.15`.
+fn factor(fact: &Fact) -> String {
+    let mut label = fact.factor.to_string();
+    if fact.uncertainty > 0. {
+        write!(label, " ± {}", fact.uncertainty).unwrap();
+    }
+    let offset = fact.offset.value();
+    if offset > 0. {
+        write!(label, " + {}", fact.offset).unwrap();
+    } else if offset < 0. {
+        write!(label, " - {}", -fact.offset.clone()).unwrap();
+    }
+    label
+}
+
+// Mermaid ends a label at `"`, so it's written as an entity.
+fn mermaid_text(text: &str) -> String {
+    text.replace('"', "#quot;")
+}
+
+fn dot_text(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::facts::parse_facts;
+    use super::*;
+
+    const FACTS: &str = "\
+unit m dim=L name=meter
+unit in alias=\"
+m = 39.37 ± 0.01 in
+degC = 1 K + 273.15
+N = 1 kg*m/s^2
+";
+
+    #[test]
+    fn draws_units_as_classes_and_facts_as_arrows() {
+        let diagram = mermaid(&parse_facts(FACTS).unwrap());
+        let lines: Vec<&str> = diagram.lines().collect();
+        assert_eq!(lines[3], "classDiagram");
+        assert_eq!(
+            lines[4..],
+            [
+                "    class u0[\"m\"] {",
+                "        dim=L",
+                "        name=meter",
+                "    }",
+                "    class u1[\"in\"] {",
+                "        alias=#quot;",
+                "    }",
+                "    class u2[\"degC\"]",
+                "    class u3[\"K\"]",
+                "    class u4[\"N\"]",
+                "    class u5[\"kg*m/s^2\"] {",
+                "        <<compound>>",
+                "    }",
+                "    u0 --> u1 : 39.37 ± 0.01",
+                "    u2 --> u3 : 1 + 273.15",
+                "    u4 --> u5 : 1",
+            ]
+        );
+    }
+
+    #[test]
+    fn draws_a_dot_digraph() {
+        let diagram = dot(&parse_facts(FACTS).unwrap());
+        assert!(diagram.starts_with("digraph units {\n"));
+        assert!(diagram.contains("    u0 [label=\"m\\ndim=L\\nname=meter\"];\n"));
+        assert!(diagram.contains("    u1 [label=\"in\\nalias=\\\"\"];\n"));
+        assert!(diagram.contains("    u5 [label=\"kg*m/s^2\", shape=box, style=dashed];\n"));
+        assert!(diagram.contains("    u2 -> u3 [label=\"1 + 273.15\"];\n"));
+        assert!(diagram.ends_with("}\n"));
+    }
+}
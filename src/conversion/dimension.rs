@@ -0,0 +1,160 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+const SUPERSCRIPT_DIGITS: [char; 10] = ['⁰', '¹', '²', '³', '⁴', '⁵', '⁶', '⁷', '⁸', '⁹'];
+
+/// A vector of exponents over base dimensions, e.g. `L·T⁻¹` for a speed.
+///
+/// Base dimensions are named by the facts file (`unit m dim=L`), so the set of
+/// them isn't fixed. Exponents of zero are never stored.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Dimension(BTreeMap<String, i32>);
+
+impl Dimension {
+    pub fn base(name: &str) -> Self {
+        Self(BTreeMap::from([(name.to_string(), 1)]))
+    }
+
+    pub fn dimensionless() -> Self {
+        Self::default()
+    }
+
+    pub fn is_dimensionless(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn exponent(&self, base: &str) -> i32 {
+        self.0.get(base).copied().unwrap_or(0)
+    }
+
+    /// The dimension raised to `exponent`, or `None` if an exponent overflows.
+    pub fn checked_powi(&self, exponent: i32) -> Option<Self> {
+        let mut result = Self::dimensionless();
+        if exponent != 0 {
+            for (base, e) in &self.0 {
+                result.0.insert(base.clone(), e.checked_mul(exponent)?);
+            }
+        }
+        Some(result)
+    }
+
+    /// The product of two dimensions, or `None` if an exponent overflows.
+    pub fn checked_mul(self, other: &Self) -> Option<Self> {
+        self.combine(other, 1)
+    }
+
+    /// The quotient of two dimensions, or `None` if an exponent overflows.
+    pub fn checked_div(self, other: &Self) -> Option<Self> {
+        self.combine(other, -1)
+    }
+
+    fn combine(mut self, other: &Self, sign: i32) -> Option<Self> {
+        for (base, e) in &other.0 {
+            let total = self.exponent(base).checked_add(sign.checked_mul(*e)?)?;
+            if total == 0 {
+                self.0.remove(base);
+            } else {
+                self.0.insert(base.clone(), total);
+            }
+        }
+        Some(self)
+    }
+}
+
+impl fmt::Display for Dimension {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_dimensionless() {
+            return write!(f, "1");
+        }
+        let parts: Vec<String> = self
+            .0
+            .iter()
+            .map(|(base, &e)| {
+                if e == 1 {
+                    base.clone()
+                } else {
+                    format!("{}{}", base, superscript(e))
+                }
+            })
+            .collect();
+        write!(f, "{}", parts.join("·"))
+    }
+}
+
+/// Writes an exponent with superscript characters, e.g. `-2` as `⁻²`.
+pub fn superscript(exponent: i32) -> String {
+    let mut s = String::new();
+    if exponent < 0 {
+        s.push('⁻');
+    }
+    for digit in exponent.unsigned_abs().to_string().bytes() {
+        s.push(SUPERSCRIPT_DIGITS[(digit - b'0') as usize]);
+    }
+    s
+}
+
+/// Reads a superscript exponent such as `²` or `⁻¹` back into a number.
+pub fn parse_superscript(s: &str) -> Option<i32> {
+    let (sign, digits) = match s.strip_prefix('⁻') {
+        Some(rest) => (-1, rest),
+        None => (1, s),
+    };
+    if digits.is_empty() {
+        return None;
+    }
+    let mut value: i32 = 0;
+    for c in digits.chars() {
+        let digit = SUPERSCRIPT_DIGITS.iter().position(|&d| d == c)? as i32;
+        value = value.checked_mul(10)?.checked_add(digit)?;
+    }
+    Some(sign * value)
+}
+
+pub fn is_superscript(c: char) -> bool {
+    c == '⁻' || SUPERSCRIPT_DIGITS.contains(&c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn displays_exponents_as_superscripts() {
+        let speed = Dimension::base("L")
+            .checked_div(&Dimension::base("T"))
+            .unwrap();
+        assert_eq!(speed.to_string(), "L·T⁻¹");
+        let force = Dimension::base("M")
+            .checked_mul(&speed)
+            .and_then(|d| d.checked_div(&Dimension::base("T")))
+            .unwrap();
+        assert_eq!(force.to_string(), "L·M·T⁻²");
+        assert_eq!(
+            Dimension::base("L").checked_powi(2).unwrap().to_string(),
+            "L²"
+        );
+        assert_eq!(speed.clone().checked_div(&speed).unwrap().to_string(), "1");
+    }
+
+    #[test]
+    fn refuses_exponents_that_overflow() {
+        let huge = Dimension::base("L").checked_powi(i32::MAX).unwrap();
+        assert_eq!(huge.clone().checked_mul(&Dimension::base("L")), None);
+        assert_eq!(huge.checked_powi(2), None);
+        assert_eq!(
+            Dimension::base("L")
+                .checked_powi(i32::MIN)
+                .unwrap()
+                .checked_div(&Dimension::base("L")),
+            None
+        );
+    }
+
+    #[test]
+    fn superscripts_round_trip() {
+        for e in [-12, -1, 2, 3, 10] {
+            assert_eq!(parse_superscript(&superscript(e)), Some(e));
+        }
+        assert_eq!(parse_superscript("⁻"), None);
+    }
+}
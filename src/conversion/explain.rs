@@ -0,0 +1,74 @@
+use super::facts::Fact;
+use super::route::{Route, RoutePolicy};
+use std::fmt;
+
+/// One ratio or offset applied while converting, and the value after it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Step {
+    /// The operation as written in the chain, e.g. `× 3.28 ft/m` or `+ 273.15 K`.
+    pub operation: String,
+    pub value: f64,
+    pub unit: String,
+    /// The facts the operation's ratio comes from. A prefix needs none.
+    pub facts: Vec<Fact>,
+}
+
+/// How a conversion was derived: the chain of operations, e.g.
+/// `2 m × 3.28 ft/m × 12 in/ft = 78.72 in`, with the value after each of them
+/// and the file and line of every fact used, and the routes passed over.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Explanation {
+    pub value: f64,
+    pub from: String,
+    pub steps: Vec<Step>,
+    pub result: f64,
+    pub to: String,
+    /// How the route was chosen where several chains of facts connect the
+    /// units.
+    pub policy: RoutePolicy,
+    /// The other routes, best first by `policy`.
+    pub rejected: Vec<Route>,
+}
+
+impl Explanation {
+    /// The whole conversion on one line.
+    pub fn chain(&self) -> String {
+        let mut chain = format!("{} {}", self.value, self.from);
+        for step in &self.steps {
+            chain.push(' ');
+            chain.push_str(&step.operation);
+        }
+        format!("{} = {} {}", chain, self.result, self.to)
+    }
+}
+
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.chain())?;
+        for step in &self.steps {
+            write!(f, "\n  {} = {} {}", step.operation, step.value, step.unit)?;
+            let cited: Vec<String> = step
+                .facts
+                .iter()
+                .map(|fact| match &fact.source {
+                    Some(source) => format!("{} ({})", fact, source),
+                    None => fact.to_string(),
+                })
+                .collect();
+            if !cited.is_empty() {
+                write!(f, "  from {}", cited.join(", "))?;
+            }
+        }
+        if !self.rejected.is_empty() {
+            write!(
+                f,
+                "\n  chose the route with {} over:",
+                self.policy.describe()
+            )?;
+            for route in &self.rejected {
+                write!(f, "\n    {}", route)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,276 @@
+use super::dimension::{is_superscript, parse_superscript, superscript};
+use super::unit_conversion::ConversionError;
+use std::fmt;
+use std::str::FromStr;
+
+/// The largest power a unit may be raised to, either way. Exact conversions
+/// raise factors to these powers, so unbounded ones would never finish.
+pub const MAX_POWER: i32 = 100;
+
+/// A unit expression such as `m/s`, `kg·m/s²` or `ft^2`: a product of unit
+/// symbols, each raised to an integer power.
+///
+/// `*`, `·` and `⋅` multiply, `/` divides by the term that follows it, and
+/// powers are written as `^2`, `^-1` or with superscripts (`²`, `⁻¹`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnitExpr {
+    terms: Vec<(String, i32)>,
+}
+
+impl UnitExpr {
+    pub fn unit(symbol: &str) -> Self {
+        Self {
+            terms: vec![(symbol.to_string(), 1)],
+        }
+    }
+
+    /// The product of `terms`, with repeated symbols combined and any that
+    /// cancel out dropped. Fails if a combined power is beyond ±[`MAX_POWER`].
+    pub fn from_terms(
+        terms: impl IntoIterator<Item = (String, i32)>,
+    ) -> Result<Self, ConversionError> {
+        let terms: Vec<_> = terms.into_iter().collect();
+        let mut expr = Self { terms: Vec::new() };
+        for (symbol, exponent) in &terms {
+            expr.push(symbol, *exponent)
+                .map_err(|reason| ConversionError::ParseError {
+                    input: Self {
+                        terms: terms.clone(),
+                    }
+                    .to_string(),
+                    position: 0,
+                    reason: reason.to_string(),
+                })?;
+        }
+        expr.terms.retain(|(_, exponent)| *exponent != 0);
+        Ok(expr)
+    }
+
+    pub fn terms(&self) -> &[(String, i32)] {
+        &self.terms
+    }
+
+    /// The symbol of a plain unit like `m`, or `None` for a compound expression.
+    pub fn as_simple(&self) -> Option<&str> {
+        match self.terms.as_slice() {
+            [(symbol, 1)] => Some(symbol),
+            _ => None,
+        }
+    }
+
+    /// The same expression with every symbol replaced by `f(symbol)`.
+    pub fn try_map_symbols<E>(
+        &self,
+        mut f: impl FnMut(&str) -> Result<String, E>,
+    ) -> Result<Self, E> {
+        let terms = self
+            .terms
+            .iter()
+            .map(|(symbol, exponent)| Ok((f(symbol)?, *exponent)))
+            .collect::<Result<_, E>>()?;
+        Ok(Self { terms })
+    }
+
+    fn push(&mut self, symbol: &str, exponent: i32) -> Result<(), &'static str> {
+        let (total, slot) = match self.terms.iter_mut().find(|(s, _)| s == symbol) {
+            Some((_, e)) => (e.checked_add(exponent), Some(e)),
+            None => (Some(exponent), None),
+        };
+        let total = total
+            .filter(|total| total.abs() <= MAX_POWER)
+            .ok_or("the power is out of range")?;
+        match slot {
+            Some(e) => *e = total,
+            None => self.terms.push((symbol.to_string(), total)),
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for UnitExpr {
+    type Err = ConversionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let is_operator = |c: char| matches!(c, '*' | '·' | '⋅' | '/' | '^');
+
+        let mut expr = UnitExpr { terms: Vec::new() };
+        let mut chars = s.char_indices().peekable();
+        let position = |chars: &mut std::iter::Peekable<std::str::CharIndices>| {
+            chars.peek().map_or(s.len(), |&(i, _)| i)
+        };
+        let invalid = |position: usize, reason: &str| ConversionError::ParseError {
+            input: s.to_string(),
+            position,
+            reason: reason.to_string(),
+        };
+        let mut sign = 1;
+        while chars.next_if(|(_, c)| c.is_whitespace()).is_some() {}
+        loop {
+            let start = position(&mut chars);
+            let symbol: String = std::iter::from_fn(|| {
+                chars.next_if(|&(_, c)| !(is_operator(c) || c.is_whitespace() || is_superscript(c)))
+            })
+            .map(|(_, c)| c)
+            .collect();
+            if symbol.is_empty() {
+                return Err(invalid(start, "expected a unit name"));
+            }
+
+            let mut exponent = 1;
+            if chars.next_if(|&(_, c)| c == '^').is_some() {
+                let start = position(&mut chars);
+                let digits: String =
+                    std::iter::from_fn(|| chars.next_if(|&(_, c)| c.is_ascii_digit() || c == '-'))
+                        .map(|(_, c)| c)
+                        .collect();
+                exponent = digits
+                    .parse()
+                    .map_err(|_| invalid(start, "expected an integer power after `^`"))?;
+            } else if chars.peek().is_some_and(|&(_, c)| is_superscript(c)) {
+                let start = position(&mut chars);
+                let digits: String =
+                    std::iter::from_fn(|| chars.next_if(|&(_, c)| is_superscript(c)))
+                        .map(|(_, c)| c)
+                        .collect();
+                exponent =
+                    parse_superscript(&digits).ok_or_else(|| invalid(start, "bad superscript"))?;
+            }
+            // `1/s` is written with a placeholder numerator.
+            if !(symbol == "1" && sign == 1 && exponent == 1) {
+                let power = exponent
+                    .checked_mul(sign)
+                    .ok_or("the power is out of range");
+                power
+                    .and_then(|power| expr.push(&symbol, power))
+                    .map_err(|reason| invalid(start, reason))?;
+            }
+
+            while chars.next_if(|(_, c)| c.is_whitespace()).is_some() {}
+            sign = match chars.next() {
+                None => break,
+                Some((_, '/')) => -1,
+                Some((_, '*' | '·' | '⋅')) => 1,
+                Some((i, c)) => return Err(invalid(i, &format!("unexpected `{}`", c))),
+            };
+            while chars.next_if(|(_, c)| c.is_whitespace()).is_some() {}
+        }
+
+        expr.terms.retain(|&(_, e)| e != 0);
+        if expr.terms.is_empty() {
+            return Err(invalid(0, "the units cancel out"));
+        }
+        Ok(expr)
+    }
+}
+
+impl fmt::Display for UnitExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let join = |positive: bool| -> Vec<String> {
+            self.terms
+                .iter()
+                .filter(|&&(_, e)| (e > 0) == positive)
+                .map(|(symbol, e)| match e.abs() {
+                    1 => symbol.clone(),
+                    e => format!("{}{}", symbol, superscript(e)),
+                })
+                .collect()
+        };
+        let (numerator, denominator) = (join(true), join(false));
+        if numerator.is_empty() {
+            write!(f, "1")?;
+        } else {
+            write!(f, "{}", numerator.join("·"))?;
+        }
+        if !denominator.is_empty() {
+            write!(f, "/{}", denominator.join("·"))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn terms(s: &str) -> Vec<(String, i32)> {
+        let expr: UnitExpr = s.parse().expect("Expected unit expression to parse");
+        expr.terms().to_vec()
+    }
+
+    #[test]
+    fn parses_products_quotients_and_powers() {
+        let owned = |t: &[(&str, i32)]| -> Vec<(String, i32)> {
+            t.iter().map(|&(s, e)| (s.to_string(), e)).collect()
+        };
+        assert_eq!(terms("m/s"), owned(&[("m", 1), ("s", -1)]));
+        assert_eq!(terms("kg·m/s²"), owned(&[("kg", 1), ("m", 1), ("s", -2)]));
+        assert_eq!(terms("kg*m/s^2"), terms("kg·m/s²"));
+        assert_eq!(terms("ft^2"), owned(&[("ft", 2)]));
+        assert_eq!(terms("1/s"), owned(&[("s", -1)]));
+        assert_eq!(terms("m·m/s"), owned(&[("m", 2), ("s", -1)]));
+    }
+
+    #[test]
+    fn rejects_malformed_expressions() {
+        for bad in ["", "m/", "m^", "m^x", "m s", "m/m"] {
+            assert!(
+                bad.parse::<UnitExpr>().is_err(),
+                "{:?} should not parse",
+                bad
+            );
+        }
+    }
+
+    #[test]
+    fn reports_where_parsing_stopped() {
+        let err = "kg*m/s^x".parse::<UnitExpr>().unwrap_err();
+        assert_eq!(
+            err,
+            ConversionError::ParseError {
+                input: "kg*m/s^x".to_string(),
+                position: 7,
+                reason: "expected an integer power after `^`".to_string(),
+            }
+        );
+        let err = "m s".parse::<UnitExpr>().unwrap_err();
+        assert!(matches!(
+            err,
+            ConversionError::ParseError { position: 2, .. }
+        ));
+    }
+
+    #[test]
+    fn refuses_powers_out_of_range() {
+        for bad in [
+            "m^2147483647*m",
+            "1/m^-2147483648",
+            "N^2000000000",
+            "ft^30000000",
+            "m^101",
+            "m^60*m^60",
+            "m⁻¹⁰¹",
+        ] {
+            assert!(
+                matches!(
+                    bad.parse::<UnitExpr>(),
+                    Err(ConversionError::ParseError { ref reason, .. })
+                        if reason == "the power is out of range"
+                ),
+                "{:?} should be out of range",
+                bad
+            );
+        }
+        assert_eq!(terms("m^100/m^100*s"), vec![("s".to_string(), 1)]);
+        assert!(
+            UnitExpr::from_terms([("m".to_string(), MAX_POWER), ("m".to_string(), 1)]).is_err()
+        );
+    }
+
+    #[test]
+    fn displays_canonically() {
+        let expr: UnitExpr = "kg*m/s^2".parse().unwrap();
+        assert_eq!(expr.to_string(), "kg·m/s²");
+        let expr: UnitExpr = "s^-1".parse().unwrap();
+        assert_eq!(expr.to_string(), "1/s");
+    }
+}
@@ -0,0 +1,538 @@
+use super::expression::UnitExpr;
+use super::number::Number;
+use super::prefix::PrefixSystem;
+use super::registry::{self, RegistryFormat};
+use super::uncertainty;
+use super::unit_conversion::ConversionError;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// A single conversion fact, read as `1 from = factor to` (e.g. `m = 3.28 ft`).
+///
+/// A fact with an `offset` is affine: `x from = (factor * x + offset) to`, as
+/// in `degC = 1 K + 273.15`.
+///
+/// Factors and offsets keep the exact number they were written as, so exact
+/// conversions see `3.28` rather than the float nearest to it.
+///
+/// A measured factor carries its standard uncertainty, written
+/// `m = 3.28 ± 0.001 ft` (or `+/-`), which uncertain conversions propagate.
+///
+/// Two facts are equal when they state the same thing, wherever they came from.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Fact {
+    pub from: String,
+    pub factor: Number,
+    /// The standard uncertainty of `factor`, 0 for an exact one.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub uncertainty: f64,
+    pub to: String,
+    #[serde(default = "no_offset", skip_serializing_if = "is_no_offset")]
+    pub offset: Number,
+    /// Where the fact was read from, if it came from a facts file.
+    #[serde(skip)]
+    pub source: Option<Source>,
+}
+
+impl PartialEq for Fact {
+    fn eq(&self, other: &Self) -> bool {
+        self.from == other.from
+            && self.factor == other.factor
+            && self.uncertainty == other.uncertainty
+            && self.to == other.to
+            && self.offset == other.offset
+    }
+}
+
+/// The file and line a fact was read from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Source {
+    /// The facts file, or `None` for facts parsed from a string.
+    pub file: Option<String>,
+    pub line: usize,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.file {
+            Some(file) => write!(f, "{}:{}", file, self.line),
+            None => write!(f, "line {}", self.line),
+        }
+    }
+}
+
+impl Fact {
+    /// The uncertainty of the factor as a fraction of it, which a prefix on
+    /// either side doesn't change.
+    pub fn relative_uncertainty(&self) -> f64 {
+        self.uncertainty / self.factor.value()
+    }
+
+    /// Checks what a fact must state to be used: a positive, finite factor
+    /// with a non-negative uncertainty, a right-hand side that parses and
+    /// doesn't contain the left-hand side, and a finite offset, only onto a
+    /// plain unit. Facts files are checked as they're parsed; facts read
+    /// some other way, like from a registry, should be checked with this.
+    pub fn validate(&self) -> Result<(), String> {
+        let factor = self.factor.value();
+        if !factor.is_finite() || factor <= 0. {
+            return Err(format!("factor must be positive, got {}", self.factor));
+        }
+        if !self.uncertainty.is_finite() || self.uncertainty < 0. {
+            return Err(format!("invalid uncertainty `{}`", self.uncertainty));
+        }
+        let expr: UnitExpr = self
+            .to
+            .parse()
+            .map_err(|e: ConversionError| e.to_string())?;
+        if expr.terms().iter().any(|(symbol, _)| *symbol == self.from) {
+            return Err(format!(
+                "`{}` cannot be defined in terms of itself",
+                self.from
+            ));
+        }
+        let offset = self.offset.value();
+        if !offset.is_finite() {
+            return Err(format!("invalid offset `{}`", self.offset));
+        }
+        if offset != 0. && expr.as_simple().is_none() {
+            return Err(NEEDS_PLAIN_UNIT.to_string());
+        }
+        Ok(())
+    }
+
+    pub fn new(from: &str, factor: f64, to: &str) -> Self {
+        Self::affine(from, factor, to, 0.)
+    }
+
+    pub fn affine(from: &str, factor: f64, to: &str, offset: f64) -> Self {
+        Self {
+            from: from.to_string(),
+            factor: factor.into(),
+            uncertainty: 0.,
+            to: to.to_string(),
+            offset: offset.into(),
+            source: None,
+        }
+    }
+}
+
+impl fmt::Display for Fact {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} = {}", self.from, self.factor)?;
+        if self.uncertainty > 0. {
+            write!(f, " ± {}", self.uncertainty)?;
+        }
+        write!(f, " {}", self.to)?;
+        let offset = self.offset.value();
+        if offset > 0. {
+            write!(f, " + {}", self.offset)?;
+        } else if offset < 0. {
+            write!(f, " - {}", self.offset.to_string().trim_start_matches('-'))?;
+        }
+        Ok(())
+    }
+}
+
+fn is_zero(uncertainty: &f64) -> bool {
+    *uncertainty == 0.
+}
+
+fn no_offset() -> Number {
+    Number::from(0.)
+}
+
+fn is_no_offset(offset: &Number) -> bool {
+    offset.value() == 0.
+}
+
+/// A `unit` line describing a unit rather than relating two of them, e.g.
+/// `unit m dim=L` or `unit ft name=foot plural=feet alias='`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UnitDecl {
+    pub symbol: String,
+    /// The base dimension the unit measures, e.g. `L` for length.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dimension: Option<String>,
+    /// The prefix families the unit accepts, e.g. SI for `km` or binary for `GiB`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub prefixes: Vec<PrefixSystem>,
+    /// The full name, e.g. `meter`. It matches in any case and takes full
+    /// prefix names, as in `Kilometers`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// The plural of `name`, if it isn't `name` followed by `s`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub plural: Option<String>,
+    /// Other spellings, e.g. `metre` or `'`. Like the symbol they match
+    /// exactly, so `Mm` and `mm` stay distinct.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<String>,
+    /// Whether the symbol and aliases also match in any case (`case=insensitive`).
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub case_insensitive: bool,
+    /// The systems of units the unit belongs to, e.g. `si` or `imperial`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub systems: Vec<String>,
+    /// Names that only mean this unit inside a mixed quantity, where they'd
+    /// otherwise clash, e.g. `m` for minutes in `1h30m`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub mixed: Vec<String>,
+}
+
+impl UnitDecl {
+    /// The `key=value` attributes of the unit's `unit` line, e.g.
+    /// `dim=L`, `prefix=si` and `name=meter`.
+    pub fn attributes(&self) -> Vec<String> {
+        let mut attributes = Vec::new();
+        let mut push = |key: &str, value: String| attributes.push(format!("{}={}", key, value));
+        if let Some(dimension) = &self.dimension {
+            push("dim", dimension.clone());
+        }
+        if !self.prefixes.is_empty() {
+            let prefixes: Vec<&str> = self.prefixes.iter().map(PrefixSystem::name).collect();
+            push("prefix", prefixes.join(","));
+        }
+        if let Some(name) = &self.name {
+            push("name", name.clone());
+        }
+        if let Some(plural) = &self.plural {
+            push("plural", plural.clone());
+        }
+        if !self.aliases.is_empty() {
+            push("alias", self.aliases.join(","));
+        }
+        if self.case_insensitive {
+            push("case", "insensitive".to_string());
+        }
+        if !self.systems.is_empty() {
+            push("system", self.systems.join(","));
+        }
+        if !self.mixed.is_empty() {
+            push("mixed", self.mixed.join(","));
+        }
+        attributes
+    }
+}
+
+/// Writes the `unit` line the declaration is read from, e.g.
+/// `unit m dim=L prefix=si name=meter`.
+impl fmt::Display for UnitDecl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unit {}", self.symbol)?;
+        for attribute in self.attributes() {
+            write!(f, " {}", attribute)?;
+        }
+        Ok(())
+    }
+}
+
+/// A `system` line naming a system of units and its other names, e.g.
+/// `system si alias=metric`. Units join a system with `system=` on their own
+/// `unit` line, whether or not it is declared.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SystemDecl {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<String>,
+}
+
+/// Everything read from a facts file, and the registry that
+/// [`registry`](super::registry) writes as JSON or TOML.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FactsFile {
+    pub units: Vec<UnitDecl>,
+    pub systems: Vec<SystemDecl>,
+    pub facts: Vec<Fact>,
+}
+
+/// Parses a facts file: one `lhs = factor rhs`, `unit <symbol> key=value...`
+/// or `system <name> alias=...` per line, `#` comments and blank lines
+/// allowed.
+///
+/// The right-hand side of a fact may be a compound unit such as `kg*m/s^2`,
+/// which defines `lhs` in terms of other units, or end in `+ offset` or
+/// `- offset` for an affine unit such as `degC = 1 K + 273.15`.
+pub fn parse_facts(source: &str) -> Result<FactsFile, ConversionError> {
+    parse_facts_from(source, None)
+}
+
+/// Parses a facts file like [`parse_facts`], recording `file` as the source of
+/// every fact and in errors.
+pub fn parse_facts_from(source: &str, file: Option<&str>) -> Result<FactsFile, ConversionError> {
+    let origin = file.map(String::from);
+    let mut file = FactsFile::default();
+    for (i, line) in source.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let at_line = |reason: String| ConversionError::InvalidFact {
+            path: origin.clone(),
+            line: i + 1,
+            text: line.to_string(),
+            reason,
+        };
+        let mut tokens = line.split_whitespace();
+        let keyword = tokens.next();
+        let declaration = tokens.next() != Some("=");
+        if keyword == Some("unit") && declaration {
+            file.units.push(parse_unit_decl(line).map_err(at_line)?);
+        } else if keyword == Some("system") && declaration {
+            file.systems.push(parse_system_decl(line).map_err(at_line)?);
+        } else {
+            let mut fact = parse_fact(line).map_err(at_line)?;
+            fact.source = Some(Source {
+                file: origin.clone(),
+                line: i + 1,
+            });
+            file.facts.push(fact);
+        }
+    }
+    Ok(file)
+}
+
+/// Reads a facts file, or a registry written as JSON or TOML if the path ends
+/// in `.json` or `.toml`.
+pub fn load_facts<P: AsRef<Path>>(path: P) -> Result<FactsFile, ConversionError> {
+    let path = path.as_ref();
+    let source = fs::read_to_string(path).map_err(|e| ConversionError::ReadFacts {
+        path: path.display().to_string(),
+        reason: e.to_string(),
+    })?;
+    let name = path.display().to_string();
+    match RegistryFormat::from_path(path) {
+        Some(format) => registry::parse(&source, format, Some(&name)),
+        None => parse_facts_from(&source, Some(&name)),
+    }
+}
+
+fn parse_unit_decl(line: &str) -> Result<UnitDecl, String> {
+    let mut tokens = line.split_whitespace().skip(1);
+    let symbol = tokens
+        .next()
+        .ok_or_else(|| "expected `unit <symbol> key=value...`".to_string())?;
+    let mut decl = UnitDecl {
+        symbol: symbol.to_string(),
+        ..UnitDecl::default()
+    };
+    for attribute in tokens {
+        let (key, value) = attribute
+            .split_once('=')
+            .ok_or_else(|| format!("expected `key=value`, got `{}`", attribute))?;
+        match key {
+            "dim" => decl.dimension = Some(value.to_string()),
+            "prefix" => {
+                for name in value.split(',') {
+                    let system = PrefixSystem::from_name(name)
+                        .ok_or_else(|| format!("unknown prefix family `{}`", name))?;
+                    decl.prefixes.push(system);
+                }
+            }
+            "name" => decl.name = Some(value.to_string()),
+            "plural" => decl.plural = Some(value.to_string()),
+            "alias" => decl
+                .aliases
+                .extend(value.split(',').filter(|a| !a.is_empty()).map(String::from)),
+            "mixed" => decl
+                .mixed
+                .extend(value.split(',').filter(|m| !m.is_empty()).map(String::from)),
+            "system" => decl
+                .systems
+                .extend(value.split(',').filter(|s| !s.is_empty()).map(String::from)),
+            "case" => {
+                decl.case_insensitive = match value {
+                    "sensitive" => false,
+                    "insensitive" => true,
+                    _ => {
+                        return Err(format!(
+                            "expected `case=sensitive` or `case=insensitive`, got `{}`",
+                            value
+                        ))
+                    }
+                }
+            }
+            _ => return Err(format!("unknown unit attribute `{}`", key)),
+        }
+    }
+    Ok(decl)
+}
+
+fn parse_system_decl(line: &str) -> Result<SystemDecl, String> {
+    let mut tokens = line.split_whitespace().skip(1);
+    let name = tokens
+        .next()
+        .ok_or_else(|| "expected `system <name> alias=...`".to_string())?;
+    let mut decl = SystemDecl {
+        name: name.to_string(),
+        ..SystemDecl::default()
+    };
+    for attribute in tokens {
+        match attribute.split_once('=') {
+            Some(("alias", value)) => decl
+                .aliases
+                .extend(value.split(',').filter(|a| !a.is_empty()).map(String::from)),
+            Some((key, _)) => return Err(format!("unknown system attribute `{}`", key)),
+            None => return Err(format!("expected `key=value`, got `{}`", attribute)),
+        }
+    }
+    Ok(decl)
+}
+
+const NEEDS_PLAIN_UNIT: &str = "an offset needs a plain unit on the right-hand side";
+
+fn parse_fact(line: &str) -> Result<Fact, String> {
+    const EXPECTED: &str = "expected `lhs = factor rhs` or `lhs = factor rhs + offset`";
+    let (lhs, rhs) = line.split_once('=').ok_or_else(|| EXPECTED.to_string())?;
+    let lhs: Vec<&str> = lhs.split_whitespace().collect();
+    // A measured factor is followed by its uncertainty, as in `3.28 ± 0.001 ft`.
+    let (rhs, uncertainty) = match uncertainty::split_uncertainty(rhs) {
+        Some((factor, rest)) => {
+            let (uncertainty, rest) = rest
+                .trim_start()
+                .split_once(char::is_whitespace)
+                .ok_or_else(|| EXPECTED.to_string())?;
+            let uncertainty = uncertainty::parse_uncertainty(uncertainty)
+                .ok_or_else(|| format!("invalid uncertainty `{}`", uncertainty))?;
+            (format!("{} {}", factor, rest), uncertainty)
+        }
+        None => (rhs.to_string(), 0.),
+    };
+    let rhs: Vec<&str> = rhs.split_whitespace().collect();
+    let (from, factor, to, offset) = match (lhs.as_slice(), rhs.as_slice()) {
+        ([from], [factor, to]) => (*from, *factor, *to, None),
+        ([from], [factor, to, sign @ ("+" | "-"), offset]) => {
+            (*from, *factor, *to, Some((*sign, *offset)))
+        }
+        _ => return Err(EXPECTED.to_string()),
+    };
+    let factor: Number = factor
+        .parse()
+        .map_err(|_| format!("invalid factor `{}`", factor))?;
+    let expr: UnitExpr = to.parse().map_err(|e: ConversionError| e.to_string())?;
+
+    let offset = match offset {
+        None => Number::from(0.),
+        Some((sign, offset)) => {
+            if expr.as_simple().is_none() {
+                return Err(NEEDS_PLAIN_UNIT.to_string());
+            }
+            let offset: Number = offset
+                .parse()
+                .map_err(|_| format!("invalid offset `{}`", offset))?;
+            if sign == "-" {
+                -offset
+            } else {
+                offset
+            }
+        }
+    };
+    let fact = Fact {
+        from: from.to_string(),
+        factor,
+        uncertainty,
+        to: to.to_string(),
+        offset,
+        source: None,
+    };
+    fact.validate()?;
+    Ok(fact)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_facts_with_comments_and_blank_lines() {
+        let source = "# distance\nm = 3.28 ft\n\nft = 12 in # exact\n";
+        let facts = parse_facts(source).expect("Expected facts to parse");
+        assert_eq!(
+            facts.facts,
+            vec![Fact::new("m", 3.28, "ft"), Fact::new("ft", 12., "in")]
+        );
+        assert_eq!(
+            facts.facts[1].source.as_ref().unwrap().to_string(),
+            "line 4"
+        );
+        let named = parse_facts_from(source, Some("distance.facts")).unwrap();
+        assert_eq!(
+            named.facts[0].source.as_ref().unwrap().to_string(),
+            "distance.facts:2"
+        );
+    }
+
+    #[test]
+    fn reports_the_line_of_a_bad_fact() {
+        let err = parse_facts("m = 3.28 ft\nft = twelve in\n").unwrap_err();
+        assert!(matches!(err, ConversionError::InvalidFact { line: 2, .. }));
+        assert_eq!(
+            err.to_string(),
+            "line 2: invalid factor `twelve` in `ft = twelve in`"
+        );
+    }
+
+    #[test]
+    fn parses_unit_declarations_and_compound_facts() {
+        let source = "unit m dim=L\nunit sec dim=T\nmph = 0.44704 m/sec\n";
+        let file = parse_facts(source).expect("Expected facts to parse");
+        assert_eq!(file.units[0].symbol, "m");
+        assert_eq!(file.units[1].dimension.as_deref(), Some("T"));
+        assert_eq!(file.facts, vec![Fact::new("mph", 0.44704, "m/sec")]);
+        assert!(parse_facts("unit m size=L\n").is_err());
+
+        let file = parse_facts("unit B dim=Info prefix=si,binary\n").unwrap();
+        assert_eq!(
+            file.units[0].prefixes,
+            vec![PrefixSystem::Si, PrefixSystem::Binary]
+        );
+
+        let file =
+            parse_facts("unit ft name=foot plural=feet alias=',foots case=insensitive\n").unwrap();
+        assert_eq!(file.units[0].name.as_deref(), Some("foot"));
+        assert_eq!(file.units[0].plural.as_deref(), Some("feet"));
+        assert_eq!(file.units[0].aliases, vec!["'", "foots"]);
+        assert!(file.units[0].case_insensitive);
+        assert_eq!(
+            file.units[0].to_string(),
+            "unit ft name=foot plural=feet alias=',foots case=insensitive"
+        );
+        assert!(parse_facts("unit ft case=upper\n").is_err());
+
+        let file = parse_facts("system si alias=metric,SI\nunit m system=si mixed=M\n").unwrap();
+        assert_eq!(file.systems[0].name, "si");
+        assert_eq!(file.systems[0].aliases, vec!["metric", "SI"]);
+        assert_eq!(file.units[0].systems, vec!["si"]);
+        assert_eq!(file.units[0].mixed, vec!["M"]);
+        assert!(parse_facts("system si name=SI\n").is_err());
+    }
+
+    #[test]
+    fn parses_affine_facts() {
+        let file = parse_facts("degC = 1 K + 273.15\nx = 2 y - 3\n").unwrap();
+        assert_eq!(
+            file.facts,
+            vec![
+                Fact::affine("degC", 1., "K", 273.15),
+                Fact::affine("x", 2., "y", -3.)
+            ]
+        );
+        assert_eq!(file.facts[1].to_string(), "x = 2 y - 3");
+        let file = parse_facts("degR = 5/9 K\n").unwrap();
+        assert_eq!(file.facts[0].to_string(), "degR = 5/9 K");
+        assert!(parse_facts("x = 2 y/s + 3\n").is_err());
+    }
+
+    #[test]
+    fn parses_measured_factors() {
+        let file = parse_facts("m = 3.28 ± 0.001 ft\nx = 2+/-0.5 y + 3\n").unwrap();
+        assert_eq!(file.facts[0].uncertainty, 0.001);
+        assert_eq!(file.facts[0].to_string(), "m = 3.28 ± 0.001 ft");
+        assert_eq!(file.facts[1].to_string(), "x = 2 ± 0.5 y + 3");
+        assert!(parse_facts("m = 3.28 ± ft\n").is_err());
+    }
+}
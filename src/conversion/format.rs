@@ -0,0 +1,302 @@
+use super::expression::UnitExpr;
+use super::graph::UnitGraph;
+use super::number;
+use num_rational::BigRational;
+use num_traits::ToPrimitive;
+
+/// How many digits of a value to write.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Precision {
+    /// The fewest digits that read back as the same `f64`, e.g. `78.72`.
+    #[default]
+    Shortest,
+    /// A fixed number of decimal places, e.g. `0.330` for 3.
+    Decimals(usize),
+    /// A number of significant figures, e.g. `0.33` or `79` for 2.
+    Significant(usize),
+}
+
+/// How a value's magnitude is written.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Notation {
+    /// `78720`
+    #[default]
+    Plain,
+    /// `7.872e4`
+    Scientific,
+    /// `78.72e3`, with the exponent a multiple of 3.
+    Engineering,
+}
+
+/// Whether a unit is written as its symbol (`in`) or its full name (`inches`).
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum UnitStyle {
+    #[default]
+    Symbol,
+    Name,
+}
+
+/// Options for writing conversion results, e.g. `78,720.00 inches` or
+/// `7.9e4 in`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Format {
+    pub precision: Precision,
+    pub notation: Notation,
+    /// Groups the digits before the decimal point in threes with this
+    /// character, as in `78,720`. Only used in plain notation.
+    pub separator: Option<char>,
+    pub unit_style: UnitStyle,
+}
+
+impl Format {
+    /// Writes `value` with these options.
+    pub fn number(&self, value: f64) -> String {
+        if !value.is_finite() {
+            return value.to_string();
+        }
+        match self.notation {
+            Notation::Plain => self.group(&self.plain(value)),
+            Notation::Scientific => {
+                let (mantissa, exponent) = self.scientific(value, 1);
+                format!("{}e{}", mantissa, exponent)
+            }
+            Notation::Engineering => {
+                let (mantissa, exponent) = self.scientific(value, 3);
+                format!("{}e{}", mantissa, exponent)
+            }
+        }
+    }
+
+    /// Writes an exact result. In plain notation a fixed number of decimals
+    /// is rounded from the exact value, and without a precision the result is
+    /// written as [`number::format_exact`] does; otherwise it is written like
+    /// the nearest `f64`.
+    pub fn exact(&self, value: &BigRational) -> String {
+        match (self.notation, self.precision) {
+            (Notation::Plain, Precision::Shortest) => {
+                number::format_exact(value, super::query::EXACT_PLACES)
+            }
+            (Notation::Plain, Precision::Decimals(places)) => {
+                self.group(&pad_decimals(number::to_decimal(value, places), places))
+            }
+            _ => self.number(value.to_f64().unwrap_or(f64::NAN)),
+        }
+    }
+
+    /// Writes `value` followed by `unit` in the chosen style. Units without a
+    /// full name are written with their symbol.
+    pub fn quantity(&self, graph: &UnitGraph, value: f64, unit: &UnitExpr) -> String {
+        format!("{} {}", self.number(value), self.unit(graph, unit, value))
+    }
+
+    /// Writes the parts of a mixed quantity, e.g. `5 ft 11 in`.
+    pub fn mixed(&self, graph: &UnitGraph, parts: &[(f64, UnitExpr)]) -> String {
+        parts
+            .iter()
+            .map(|(value, unit)| self.quantity(graph, *value, unit))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Writes `unit` in the chosen style, plural or not to agree with `value`.
+    pub fn unit(&self, graph: &UnitGraph, unit: &UnitExpr, value: f64) -> String {
+        match self.unit_style {
+            UnitStyle::Symbol => unit.to_string(),
+            UnitStyle::Name => graph.describe(unit, value),
+        }
+    }
+
+    fn plain(&self, value: f64) -> String {
+        match self.precision {
+            Precision::Shortest => value.to_string(),
+            Precision::Decimals(places) => format!("{:.*}", places, value),
+            Precision::Significant(figures) => {
+                if value == 0. {
+                    return format!("{:.*}", figures.saturating_sub(1), value);
+                }
+                // Round in scientific notation, which rounds by significant
+                // figures, then write the digits out in full.
+                let (digits, exponent) = decompose(&format!("{:.*e}", figures.max(1) - 1, value));
+                place_point(&digits, exponent + 1)
+            }
+        }
+    }
+
+    // The mantissa and an exponent that is a multiple of `step`.
+    fn scientific(&self, value: f64, step: i32) -> (String, i32) {
+        let formatted = |value: f64, exponent: i32| -> String {
+            let shift = (exponent.rem_euclid(step)) as usize;
+            match self.precision {
+                Precision::Shortest => format!("{:e}", value),
+                Precision::Decimals(places) => format!("{:.*e}", places + shift, value),
+                Precision::Significant(figures) => format!("{:.*e}", figures.max(1) - 1, value),
+            }
+        };
+        // Rounding can carry into the next power of ten (9.99e2 -> 1.0e3),
+        // which moves the point in engineering notation, so round again.
+        let mut exponent = decompose(&format!("{:e}", value)).1;
+        let (digits, rounded) = loop {
+            let (digits, rounded) = decompose(&formatted(value, exponent));
+            if rounded == exponent {
+                break (digits, rounded);
+            }
+            exponent = rounded;
+        };
+        let base = rounded.div_euclid(step) * step;
+        (place_point(&digits, rounded - base + 1), base)
+    }
+
+    fn group(&self, number: &str) -> String {
+        let Some(separator) = self.separator else {
+            return number.to_string();
+        };
+        let (sign, unsigned) = match number.strip_prefix('-') {
+            Some(unsigned) => ("-", unsigned),
+            None => ("", number),
+        };
+        let (whole, fraction) = match unsigned.split_once('.') {
+            Some((whole, fraction)) => (whole, Some(fraction)),
+            None => (unsigned, None),
+        };
+        let mut grouped = String::new();
+        for (i, digit) in whole.chars().enumerate() {
+            if i > 0 && (whole.len() - i) % 3 == 0 {
+                grouped.push(separator);
+            }
+            grouped.push(digit);
+        }
+        match fraction {
+            Some(fraction) => format!("{}{}.{}", sign, grouped, fraction),
+            None => format!("{}{}", sign, grouped),
+        }
+    }
+}
+
+// Splits Rust's `{:e}` output, e.g. `-7.872e4`, into its signed digits
+// (`-7872`) and exponent (4).
+fn decompose(scientific: &str) -> (String, i32) {
+    let (mantissa, exponent) = scientific
+        .split_once('e')
+        .expect("`{:e}` output has an exponent");
+    (mantissa.replace('.', ""), exponent.parse().unwrap_or(0))
+}
+
+// Writes signed `digits` with the decimal point after the first `whole` of
+// them, padding with zeros as needed: `7872` with 2 is `78.72`.
+fn place_point(digits: &str, whole: i32) -> String {
+    let (sign, digits) = match digits.strip_prefix('-') {
+        Some(digits) => ("-", digits),
+        None => ("", digits),
+    };
+    let number = if whole <= 0 {
+        format!("0.{}{}", "0".repeat(whole.unsigned_abs() as usize), digits)
+    } else if whole as usize >= digits.len() {
+        format!("{}{}", digits, "0".repeat(whole as usize - digits.len()))
+    } else {
+        let (int, fraction) = digits.split_at(whole as usize);
+        format!("{}.{}", int, fraction)
+    };
+    format!("{}{}", sign, number)
+}
+
+// `to_decimal` drops trailing zeros; a fixed number of decimals keeps them.
+fn pad_decimals(decimal: String, places: usize) -> String {
+    if places == 0 {
+        return decimal;
+    }
+    let written = decimal
+        .split_once('.')
+        .map_or(0, |(_, fraction)| fraction.len());
+    let point = if written == 0 { "." } else { "" };
+    format!("{}{}{}", decimal, point, "0".repeat(places - written))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with(precision: Precision, notation: Notation) -> Format {
+        Format {
+            precision,
+            notation,
+            ..Format::default()
+        }
+    }
+
+    #[test]
+    fn writes_precision_and_notation() {
+        let plain = Notation::Plain;
+        assert_eq!(
+            Format::default().number(0.33028455284552843),
+            "0.33028455284552843"
+        );
+        assert_eq!(
+            with(Precision::Decimals(3), plain).number(0.3302845),
+            "0.330"
+        );
+        assert_eq!(
+            with(Precision::Significant(2), plain).number(0.3302845),
+            "0.33"
+        );
+        assert_eq!(
+            with(Precision::Significant(2), plain).number(78720.),
+            "79000"
+        );
+        assert_eq!(
+            with(Precision::Significant(3), plain).number(-0.0012345),
+            "-0.00123"
+        );
+
+        let sci = Notation::Scientific;
+        assert_eq!(with(Precision::Shortest, sci).number(78720.), "7.872e4");
+        assert_eq!(with(Precision::Significant(2), sci).number(78720.), "7.9e4");
+        assert_eq!(
+            with(Precision::Decimals(1), sci).number(0.0009999),
+            "1.0e-3"
+        );
+
+        let eng = Notation::Engineering;
+        assert_eq!(with(Precision::Shortest, eng).number(78720.), "78.72e3");
+        assert_eq!(with(Precision::Significant(1), eng).number(78720.), "80e3");
+        assert_eq!(
+            with(Precision::Decimals(2), eng).number(0.00033),
+            "330.00e-6"
+        );
+        assert_eq!(with(Precision::Decimals(1), eng).number(999.96), "1.0e3");
+    }
+
+    #[test]
+    fn writes_separators_and_units() {
+        let format = Format {
+            precision: Precision::Decimals(2),
+            separator: Some(','),
+            ..Format::default()
+        };
+        assert_eq!(format.number(-1234567.891), "-1,234,567.89");
+        assert_eq!(format.number(123.), "123.00");
+
+        let graph = UnitGraph::standard();
+        let inches: UnitExpr = "in".parse().unwrap();
+        assert_eq!(
+            Format::default().quantity(&graph, 78.72, &inches),
+            "78.72 in"
+        );
+        let named = Format {
+            unit_style: UnitStyle::Name,
+            ..Format::default()
+        };
+        assert_eq!(named.quantity(&graph, 78.72, &inches), "78.72 inches");
+
+        let third = BigRational::new(1.into(), 3.into());
+        assert_eq!(Format::default().exact(&third), "1/3 ≈ 0.333333333333333");
+        assert_eq!(
+            with(Precision::Decimals(4), Notation::Plain).exact(&third),
+            "0.3333"
+        );
+        let eighth = BigRational::new(1.into(), 8.into());
+        assert_eq!(
+            with(Precision::Decimals(5), Notation::Plain).exact(&eighth),
+            "0.12500"
+        );
+    }
+}
@@ -0,0 +1,2362 @@
+use super::compiled::{CompiledGraph, Entry, UnitId};
+use super::consistency::Inconsistency;
+use super::dimension::Dimension;
+use super::explain::{Explanation, Step};
+use super::expression::{UnitExpr, MAX_POWER};
+use super::facts::{self, Fact, FactsFile, SystemDecl, UnitDecl};
+use super::number::{Number, Scalar};
+use super::prefix::{self, Prefix, PrefixSystem};
+use super::rates::{self, Rate};
+use super::route::{Route, RoutePolicy};
+use super::unit_conversion::ConversionError;
+use chrono::{Local, NaiveDate};
+use num_rational::BigRational;
+use num_traits::ToPrimitive;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// The facts from the README, used when no facts file is given.
+const STANDARD_FACTS: &str = include_str!("../../facts/standard.facts");
+
+/// How many routes between two units `explain` considers at most.
+const MAX_ROUTES: usize = 16;
+
+/// How many cycles closed by one fact `inconsistencies` checks at most.
+const MAX_CYCLES: usize = 256;
+
+/// How many steps a search for routes or cycles takes at most, so a densely
+/// connected graph can't keep it going for ever.
+const MAX_SEARCH_STEPS: usize = 100_000;
+
+// Every fact is stored twice, once in each direction. Walking an edge
+// backwards divides by the factor instead of multiplying by its reciprocal,
+// so `in -> m` computes `v / 12 / 3.28` exactly like the old match arms did.
+#[derive(Debug, Clone, Copy)]
+struct Edge {
+    to: usize,
+    fact: usize,
+    inverse: bool,
+}
+
+// The units a fact connects after resolving prefixes, and its factor and
+// offset between the unprefixed units. The offset of an affine fact only
+// applies to absolute temperatures, not to differences between them.
+#[derive(Debug, Clone)]
+struct Link {
+    from: usize,
+    to: usize,
+    factor: Number,
+    offset: Number,
+    relative_uncertainty: f64,
+}
+
+// A unit name after lookup: `km` is `m` with a prefix of 1000, and `ΔdegC` is
+// the difference form of `degC`.
+#[derive(Debug, Clone, Copy)]
+struct UnitRef {
+    unit: usize,
+    prefix: Option<&'static Prefix>,
+    difference: bool,
+}
+
+impl UnitRef {
+    fn scale(&self) -> f64 {
+        prefix_factor(self.prefix)
+    }
+}
+
+// A fact whose right-hand side is a compound unit, e.g. `N = 1 kg*m/sec^2`.
+// It gives `unit` (and everything connected to it) a derived dimension
+// instead of adding an edge to the graph. `factor` already accounts for a
+// prefix on the left-hand side.
+#[derive(Debug, Clone)]
+struct Definition {
+    unit: usize,
+    factor: Number,
+    fact: Fact,
+    expr: UnitExpr,
+}
+
+// A measured fact a factor is computed with: a link, by its index in
+// `links`, or a definition, by its index in `definitions`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Measured {
+    Link(usize),
+    Definition(usize),
+}
+
+// What resolving a unit expression keeps track of: the defined units being
+// resolved, to catch circular definitions, and for each measured fact its
+// relative uncertainty and how many times the factors multiply by it in net.
+// `ft/s` to `in/s` through `m = 3.28 ± 0.001 ft` divides by the fact on
+// both sides, so its uncertainty cancels rather than counting twice.
+#[derive(Debug, Default)]
+struct Resolution {
+    visiting: Vec<usize>,
+    measured: BTreeMap<Measured, (i32, f64)>,
+}
+
+impl Resolution {
+    fn measure(&mut self, fact: Measured, power: i32, relative_uncertainty: f64) {
+        if relative_uncertainty > 0. {
+            self.measured
+                .entry(fact)
+                .or_insert((0, relative_uncertainty))
+                .0 += power;
+        }
+    }
+
+    // One, known to within the combined uncertainty of the facts measured.
+    fn uncertainty<T: Scalar>(&self) -> T {
+        let one = Number::from(1.);
+        self.measured
+            .values()
+            .filter(|(power, _)| *power != 0)
+            .fold(T::one(), |acc, (power, relative)| {
+                acc * T::measured(&one, *relative).powi(*power)
+            })
+    }
+}
+
+/// Units are nodes and facts are edges. A query is answered by finding a path
+/// between the two units and applying the ratio of each edge along it.
+///
+/// Every connected group of units measures one dimension: a base dimension
+/// declared with `unit m dim=L`, a derived one from a compound fact like
+/// `N = 1 kg*m/sec^2`, or, if neither, a base dimension of its own named after
+/// its first unit. Compound units are converted by reducing each of their
+/// units to that group's declared or defining unit.
+///
+/// Units declared with `prefix=si` or `prefix=binary` also answer to every
+/// prefixed form of their name (`km`, `µs`, `GiB`) without being stored.
+///
+/// A unit is known by its symbol, which is also how it is written in output,
+/// and by the aliases and full names its `unit` line gives it. Symbols and
+/// aliases match exactly (`Mm` is not `mm`) unless the unit is declared
+/// `case=insensitive`; full names and their plurals match in any case and
+/// take full prefix names (`Kilometers`).
+///
+/// A unit defined by an affine fact (`degC = 1 K + 273.15`) is an absolute
+/// temperature: its offset applies when converting it to another absolute
+/// scale. `ΔdegC` (or `delta_degC`) is a difference of it, which converts by
+/// the factor alone and is the only form allowed in compound units.
+///
+/// Conversions are generic over [`Scalar`]: `f64` for speed, or `BigRational`
+/// to carry the exact ratios the facts were written with along the path.
+///
+/// Rates that change over time, such as exchange rates, are kept apart from
+/// the facts. [`UnitGraph::as_of`] gives the graph with the rates in effect
+/// on a date added as facts.
+#[derive(Debug, Clone, Default)]
+pub struct UnitGraph {
+    index: HashMap<String, usize>,
+    aliases: HashMap<String, usize>,
+    // Lowercased full names, plurals, and the symbols and aliases of
+    // case-insensitive units.
+    folded: HashMap<String, usize>,
+    // The full name and plural of each unit that has one.
+    names: HashMap<usize, (String, String)>,
+    units: Vec<String>,
+    edges: Vec<Vec<Edge>>,
+    facts: Vec<Fact>,
+    links: Vec<Link>,
+    definitions: Vec<Definition>,
+    dimensions: HashMap<usize, String>,
+    prefixes: HashMap<usize, Vec<PrefixSystem>>,
+    systems: HashMap<usize, Vec<String>>,
+    // Every declared system name and alias, lowercased, to the system's name.
+    system_names: HashMap<String, String>,
+    mixed: HashMap<String, Vec<usize>>,
+    // The `unit` and `system` lines as given, for writing the registry back.
+    declarations: Vec<UnitDecl>,
+    system_declarations: Vec<SystemDecl>,
+    declared: HashSet<usize>,
+    affine: HashSet<usize>,
+    rates: Vec<Rate>,
+    // The anchor of each unit's connected group, worked out for every unit
+    // at once when first needed and cleared whenever units, facts or
+    // dimensions change.
+    anchors: OnceLock<Vec<Result<usize, ConversionError>>>,
+}
+
+/// Where a graph is loaded from: a facts file or registry, or the standard
+/// facts if `facts` is `None`, and a CSV of dated rates taken as of `as_of`,
+/// or today if that is `None`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GraphSource {
+    pub facts: Option<String>,
+    pub rates: Option<String>,
+    pub as_of: Option<NaiveDate>,
+}
+
+impl GraphSource {
+    /// Loads the facts and adds the rates in effect on the date.
+    pub fn load(&self) -> Result<UnitGraph, ConversionError> {
+        let graph = match &self.facts {
+            Some(path) => UnitGraph::from_facts_file(path)?,
+            None => UnitGraph::standard(),
+        };
+        self.add_rates(graph)
+    }
+
+    /// Adds the rates in effect on the date to `graph`, which may hold other
+    /// facts than the source's own.
+    pub fn add_rates(&self, mut graph: UnitGraph) -> Result<UnitGraph, ConversionError> {
+        if let Some(path) = &self.rates {
+            graph.add_rates(rates::load_rates(path)?)?;
+        }
+        if graph.rates().is_empty() {
+            return Ok(graph);
+        }
+        graph.as_of(self.as_of.unwrap_or_else(|| Local::now().date_naive()))
+    }
+}
+
+impl UnitGraph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_facts<I: IntoIterator<Item = Fact>>(facts: I) -> Self {
+        let mut graph = Self::new();
+        for fact in facts {
+            graph.add_fact(fact);
+        }
+        graph
+    }
+
+    /// The graph built from `facts/standard.facts`, which holds the README facts.
+    pub fn standard() -> Self {
+        let file = facts::parse_facts_from(STANDARD_FACTS, Some("facts/standard.facts"))
+            .expect("standard facts parse");
+        Self::build(file).expect("standard facts are valid")
+    }
+
+    pub fn from_facts_file<P: AsRef<Path>>(path: P) -> Result<Self, ConversionError> {
+        Self::build(facts::load_facts(path)?)
+    }
+
+    /// Builds a graph from a parsed facts file, checking that every unit has
+    /// exactly one dimension, that compound facts only use known units, and
+    /// that no unit name can also be read as a prefixed unit.
+    pub fn build(file: FactsFile) -> Result<Self, ConversionError> {
+        let mut graph = Self::new();
+        graph.define(file)?;
+        Ok(graph)
+    }
+
+    /// Adds the units and facts of `file` with the same checks as
+    /// [`UnitGraph::build`]. If they fail the graph is left as it was.
+    pub fn define(&mut self, file: FactsFile) -> Result<(), ConversionError> {
+        let mut graph = self.clone();
+        for decl in file.units {
+            graph.declare(decl)?;
+        }
+        for decl in file.systems {
+            graph.declare_system(decl)?;
+        }
+        // The left-hand side of a fact names a unit of its own, even if a fact
+        // further up already refers to it.
+        for fact in &file.facts {
+            if graph.find(&fact.from).is_none() {
+                graph.intern(&fact.from);
+            }
+        }
+        for fact in file.facts {
+            graph.add_fact(fact);
+        }
+        graph.check_prefix_collisions()?;
+        for unit in 0..graph.units.len() {
+            graph.resolve_unit::<f64>(
+                unit,
+                RoutePolicy::default(),
+                &mut Resolution::default(),
+                1,
+            )?;
+        }
+        *self = graph;
+        Ok(())
+    }
+
+    /// Declares a unit and its names. A name already taken by another unit
+    /// is an error.
+    pub fn declare(&mut self, decl: UnitDecl) -> Result<(), ConversionError> {
+        let unit = self.intern(&decl.symbol);
+        self.anchors.take();
+        self.declared.insert(unit);
+        self.declarations.push(decl.clone());
+        if let Some(dimension) = decl.dimension {
+            self.dimensions.insert(unit, dimension);
+        }
+        if !decl.prefixes.is_empty() {
+            self.prefixes.insert(unit, decl.prefixes);
+        }
+        if !decl.systems.is_empty() {
+            self.systems.insert(unit, decl.systems);
+        }
+        for name in decl.mixed {
+            self.mixed.entry(name).or_default().push(unit);
+        }
+
+        let mut folded = Vec::new();
+        for alias in &decl.aliases {
+            self.claim(alias, unit)?;
+            self.aliases.insert(alias.clone(), unit);
+        }
+        if decl.case_insensitive {
+            folded.push(decl.symbol.clone());
+            folded.extend(decl.aliases);
+        }
+        if let Some(name) = decl.name {
+            let plural = decl.plural.unwrap_or_else(|| format!("{}s", name));
+            folded.extend([name.clone(), plural.clone()]);
+            self.names.insert(unit, (name, plural));
+        }
+        for name in folded {
+            let name = name.to_lowercase();
+            match self.folded.get(&name) {
+                Some(&other) if other != unit => return Err(self.duplicate(&name, other, unit)),
+                _ => self.folded.insert(name, unit),
+            };
+        }
+        Ok(())
+    }
+
+    /// Declares a system of units and its aliases, e.g. `si` also known as
+    /// `metric`. A name already taken by another system is an error.
+    pub fn declare_system(&mut self, decl: SystemDecl) -> Result<(), ConversionError> {
+        for name in std::iter::once(&decl.name).chain(&decl.aliases) {
+            match self.system_names.get(&name.to_lowercase()) {
+                Some(other) if *other != decl.name => {
+                    return Err(ConversionError::DuplicateName {
+                        name: name.clone(),
+                        first: other.clone(),
+                        second: decl.name.clone(),
+                    })
+                }
+                _ => self
+                    .system_names
+                    .insert(name.to_lowercase(), decl.name.clone()),
+            };
+        }
+        self.system_declarations.push(decl);
+        Ok(())
+    }
+
+    /// The names of every system of units, sorted: those declared with a
+    /// `system` line and those units join with `system=`.
+    pub fn systems(&self) -> Vec<&str> {
+        let mut systems: Vec<&str> = self
+            .systems
+            .values()
+            .flatten()
+            .chain(self.system_names.values())
+            .map(String::as_str)
+            .collect();
+        systems.sort();
+        systems.dedup();
+        systems
+    }
+
+    /// The name of the system `name` refers to, in any case or by an alias,
+    /// e.g. `si` for `SI` or `metric`.
+    pub fn system(&self, name: &str) -> Result<&str, ConversionError> {
+        if let Some(system) = self.system_names.get(&name.to_lowercase()) {
+            return Ok(system);
+        }
+        let systems = self.systems();
+        systems
+            .iter()
+            .find(|system| system.eq_ignore_ascii_case(name))
+            .copied()
+            .ok_or_else(|| ConversionError::UnknownSystem {
+                name: name.to_string(),
+                known: systems.iter().map(|system| system.to_string()).collect(),
+            })
+    }
+
+    /// The symbols of the units in `system`, in the order they were declared.
+    pub fn system_units(&self, system: &str) -> Result<Vec<&str>, ConversionError> {
+        let system = self.system(system)?;
+        Ok(self
+            .units
+            .iter()
+            .enumerate()
+            .filter(|(unit, _)| self.in_system(*unit, system))
+            .map(|(_, symbol)| symbol.as_str())
+            .collect())
+    }
+
+    /// Adds rates that hold for a range of dates. Their units are declared
+    /// right away, but only convert through the rates on a graph from
+    /// [`UnitGraph::as_of`].
+    pub fn add_rates(&mut self, rates: Vec<Rate>) -> Result<(), ConversionError> {
+        let mut units = Vec::new();
+        for rate in &rates {
+            for symbol in [&rate.from, &rate.to] {
+                if self.find(symbol).is_none()
+                    && !units.iter().any(|u: &UnitDecl| u.symbol == *symbol)
+                {
+                    units.push(UnitDecl {
+                        symbol: symbol.clone(),
+                        ..UnitDecl::default()
+                    });
+                }
+            }
+        }
+        self.define(FactsFile {
+            units,
+            ..FactsFile::default()
+        })?;
+        self.rates.extend(rates);
+        Ok(())
+    }
+
+    pub fn rates(&self) -> &[Rate] {
+        &self.rates
+    }
+
+    /// The graph with the rates in effect on `date` added as facts, so
+    /// conversions through them use that day's rate.
+    pub fn as_of(&self, date: NaiveDate) -> Result<Self, ConversionError> {
+        let mut graph = self.clone();
+        let facts = rates::in_effect(&self.rates, date)
+            .into_iter()
+            .map(Rate::fact)
+            .collect();
+        graph.define(FactsFile {
+            facts,
+            ..FactsFile::default()
+        })?;
+        Ok(graph)
+    }
+
+    /// Adds a fact. Names that aren't units yet are read as a prefixed unit
+    /// when possible (`mi = 1.609344 km`) and become new units otherwise.
+    pub fn add_fact(&mut self, fact: Fact) {
+        self.anchors.take();
+        let (from, from_prefix) = self.intern_or_prefixed(&fact.from);
+        let from_prefix = Number::from(prefix_factor(from_prefix));
+        match fact.to.parse::<UnitExpr>() {
+            Ok(expr) if expr.as_simple().is_none() => {
+                self.definitions.push(Definition {
+                    unit: from,
+                    factor: scaled(&fact.factor, &Number::from(1.), &from_prefix),
+                    fact,
+                    expr,
+                });
+                return;
+            }
+            _ => (),
+        }
+
+        let (to, to_prefix) = self.intern_or_prefixed(&fact.to);
+        let to_prefix = Number::from(prefix_factor(to_prefix));
+        // 1 from = factor to, so 1 unprefixed `from` = factor * to_prefix / from_prefix `to`.
+        let factor = scaled(&fact.factor, &to_prefix, &from_prefix);
+        let offset = scaled(&fact.offset, &to_prefix, &Number::from(1.));
+        if offset.value() != 0. {
+            self.affine.insert(from);
+        }
+        let id = self.facts.len();
+        self.edges[from].push(Edge {
+            to,
+            fact: id,
+            inverse: false,
+        });
+        self.edges[to].push(Edge {
+            to: from,
+            fact: id,
+            inverse: true,
+        });
+        self.links.push(Link {
+            from,
+            to,
+            factor,
+            offset,
+            relative_uncertainty: fact.relative_uncertainty(),
+        });
+        self.facts.push(fact);
+    }
+
+    /// Finds the unit a name refers to and the factor of its prefix, e.g.
+    /// `km` is `m` with a factor of 1000. Exact unit names win over prefixed
+    /// readings; a name with several prefixed readings is an error.
+    pub fn lookup(&self, name: &str) -> Result<(&str, f64), ConversionError> {
+        let unit = self.lookup_id(name)?;
+        Ok((&self.units[unit.unit], unit.scale()))
+    }
+
+    /// Parses a unit such as `mi/hr` or `meters`, written with symbols, and
+    /// checks that the graph knows every unit in it.
+    pub fn parse_unit(&self, unit: &str) -> Result<UnitExpr, ConversionError> {
+        let expr: UnitExpr = unit.parse()?;
+        match expr
+            .terms()
+            .iter()
+            .find(|(symbol, _)| !self.contains(symbol))
+        {
+            Some((symbol, _)) => Err(self.unknown_unit(symbol)),
+            None => self.canonical(&expr),
+        }
+    }
+
+    /// Rewrites every unit in `expr` with its symbol, e.g. `meters/hour` as
+    /// `m/hr` and `kilometers` as `km`.
+    pub fn canonical(&self, expr: &UnitExpr) -> Result<UnitExpr, ConversionError> {
+        expr.try_map_symbols(|name| {
+            let unit = self.lookup_id(name)?;
+            let difference = if unit.difference { "Δ" } else { "" };
+            let prefix = unit.prefix.map_or("", |prefix| prefix.symbol);
+            Ok(format!("{}{}{}", difference, prefix, self.units[unit.unit]))
+        })
+    }
+
+    /// How to write `value` of `expr` for people: the full name, pluralized
+    /// unless `value` is 1, for a plain unit that has one (`78.72 inches`),
+    /// and the canonical symbols otherwise.
+    pub fn describe(&self, expr: &UnitExpr, value: f64) -> String {
+        let named = expr
+            .as_simple()
+            .and_then(|name| self.lookup_id(name).ok())
+            .filter(|unit| !unit.difference)
+            .and_then(|unit| Some((unit.prefix, self.names.get(&unit.unit)?)));
+        match named {
+            Some((prefix, (singular, plural))) => {
+                let name = if value == 1. { singular } else { plural };
+                format!("{}{}", prefix.map_or("", |prefix| prefix.name), name)
+            }
+            None => self
+                .canonical(expr)
+                .map_or_else(|_| expr.to_string(), |expr| expr.to_string()),
+        }
+    }
+
+    pub fn facts(&self) -> &[Fact] {
+        &self.facts
+    }
+
+    /// The unit and system declarations and facts the graph was built from,
+    /// which [`UnitGraph::build`] turns back into the same graph.
+    pub fn registry(&self) -> FactsFile {
+        FactsFile {
+            units: self.declarations.clone(),
+            systems: self.system_declarations.clone(),
+            facts: self
+                .facts
+                .iter()
+                .chain(self.definitions.iter().map(|d| &d.fact))
+                .cloned()
+                .collect(),
+        }
+    }
+
+    /// Whether `unit` names a unit, directly or with a prefix. An ambiguous
+    /// name counts, since [`UnitGraph::lookup`] explains what's wrong with it.
+    pub fn contains(&self, unit: &str) -> bool {
+        self.find(unit).is_some()
+            || self.difference_of(unit).is_some()
+            || !self.prefixed_readings(unit).is_empty()
+    }
+
+    pub fn units(&self) -> impl Iterator<Item = &str> {
+        self.units.iter().map(String::as_str)
+    }
+
+    /// Whether `expr` is an absolute temperature with an offset, like `degC`,
+    /// rather than a difference of one (`ΔdegC`) or any other unit.
+    pub fn is_absolute_temperature(&self, expr: &UnitExpr) -> bool {
+        expr.as_simple()
+            .and_then(|name| self.lookup_id(name).ok())
+            .is_some_and(|unit| self.is_absolute(&unit))
+    }
+
+    /// Every name a unit can be written with, sorted: symbols, aliases, full
+    /// names and plurals, and the `Δ` forms of absolute temperatures.
+    /// Prefixed forms aren't listed.
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = (0..self.units.len())
+            .flat_map(|unit| self.forms(unit))
+            .collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    /// The unit symbols grouped by the dimension they measure, in the order
+    /// the dimensions first appear.
+    pub fn units_by_dimension(&self) -> Vec<(Dimension, Vec<&str>)> {
+        let mut groups: Vec<(Dimension, Vec<&str>)> = Vec::new();
+        for symbol in &self.units {
+            let Ok(dimension) = self.dimension(&UnitExpr::unit(symbol)) else {
+                continue;
+            };
+            match groups.iter_mut().find(|(d, _)| *d == dimension) {
+                Some((_, symbols)) => symbols.push(symbol),
+                None => groups.push((dimension, vec![symbol])),
+            }
+        }
+        groups
+    }
+
+    /// Known names close to `name` by edit distance, best first: unit names,
+    /// the `Δ` forms of absolute temperatures, and, when none of those is
+    /// close, prefixed forms whose unit is close to the part after a prefix
+    /// (`kmm` -> `km`).
+    pub fn suggest(&self, name: &str) -> Vec<String> {
+        const MAX_SUGGESTIONS: usize = 3;
+        let mut scored: Vec<(usize, String)> = Vec::new();
+        for unit in 0..self.units.len() {
+            for form in self.forms(unit) {
+                if let Some(distance) = closeness(name, &form) {
+                    scored.push((distance, form));
+                }
+            }
+        }
+        if scored.is_empty() {
+            for (prefix, rest) in prefix::split_prefix(name) {
+                for (unit, systems) in &self.prefixes {
+                    let candidate = &self.units[*unit];
+                    if let Some(distance) = closeness(rest, candidate) {
+                        if systems.contains(&prefix.system) {
+                            scored.push((distance, format!("{}{}", prefix.symbol, candidate)));
+                        }
+                    }
+                }
+            }
+        }
+        scored.sort();
+        scored.dedup_by(|a, b| a.1 == b.1);
+        // A name that only differs in case is what was meant.
+        if scored.first().is_some_and(|&(distance, _)| distance == 0) {
+            scored.retain(|&(distance, _)| distance == 0);
+        }
+        scored
+            .into_iter()
+            .map(|(_, candidate)| candidate)
+            .take(MAX_SUGGESTIONS)
+            .collect()
+    }
+
+    /// The symbols of the units `name` stands for inside a mixed quantity
+    /// (`mixed=` on their `unit` lines), e.g. `min` for `m` in `1h30m`.
+    pub fn mixed_names(&self, name: &str) -> Vec<&str> {
+        self.mixed.get(name).map_or_else(Vec::new, |units| {
+            units
+                .iter()
+                .map(|&unit| self.units[unit].as_str())
+                .collect()
+        })
+    }
+
+    /// The error for a name that isn't a unit, with suggestions.
+    pub fn unknown_unit(&self, name: &str) -> ConversionError {
+        ConversionError::UnknownUnit {
+            name: name.to_string(),
+            suggestions: self.suggest(name),
+        }
+    }
+
+    /// Whether some chain of facts connects the two units.
+    pub fn is_connected(&self, from: &str, to: &str) -> bool {
+        match (self.lookup_id(from), self.lookup_id(to)) {
+            (Ok(from), Ok(to)) => self
+                .path(from.unit, to.unit, RoutePolicy::default())
+                .is_some(),
+            _ => false,
+        }
+    }
+
+    /// Converts `value` from one unit to another, or returns `None` if either
+    /// unit is unknown, no chain of facts connects them, or one is an absolute
+    /// temperature and the other a temperature difference.
+    pub fn convert<T: Scalar>(&self, value: T, from: &str, to: &str) -> Option<T> {
+        self.convert_along(value, from, to, RoutePolicy::default())
+    }
+
+    fn convert_along<T: Scalar>(
+        &self,
+        value: T,
+        from: &str,
+        to: &str,
+        policy: RoutePolicy,
+    ) -> Option<T> {
+        let from = self.lookup_id(from).ok()?;
+        let to = self.lookup_id(to).ok()?;
+        let offsets = self.is_absolute(&from) || self.is_absolute(&to);
+        if offsets && (from.difference || to.difference) {
+            return None;
+        }
+        let path = self.path(from.unit, to.unit, policy)?;
+        let from_prefix = Number::from(from.scale());
+        let to_prefix = Number::from(to.scale());
+
+        if offsets {
+            // Offsets are in unprefixed units, so prefixes can't be folded together.
+            let value = value * T::of(&from_prefix);
+            let converted = path
+                .iter()
+                .fold(value, |acc, edge| self.step(edge, acc, true));
+            return Some(converted / T::of(&to_prefix));
+        }
+        let one = Number::from(1.);
+        let scale = T::of(&scaled(&one, &from_prefix, &to_prefix));
+        let value = if scale == T::one() {
+            value
+        } else {
+            value * scale
+        };
+        Some(
+            path.iter()
+                .fold(value, |acc, edge| self.step(edge, acc, false)),
+        )
+    }
+
+    /// The dimension of a unit expression such as `m/sec`.
+    pub fn dimension(&self, expr: &UnitExpr) -> Result<Dimension, ConversionError> {
+        Ok(self
+            .resolve_expr::<f64>(expr, RoutePolicy::default(), &mut Resolution::default(), 1)?
+            .1)
+    }
+
+    /// Converts `value` between two unit expressions of the same dimension.
+    /// Plain units connected by facts are converted along the path between
+    /// them, anything else through the units their dimensions are based on.
+    pub fn convert_expr<T: Scalar>(
+        &self,
+        value: T,
+        from: &UnitExpr,
+        to: &UnitExpr,
+    ) -> Result<T, ConversionError> {
+        self.convert_expr_by(value, from, to, RoutePolicy::default())
+    }
+
+    /// Converts like [`UnitGraph::convert_expr`], choosing between chains of
+    /// facts by `policy` where there are several.
+    pub fn convert_expr_by<T: Scalar>(
+        &self,
+        value: T,
+        from: &UnitExpr,
+        to: &UnitExpr,
+        policy: RoutePolicy,
+    ) -> Result<T, ConversionError> {
+        // Both sides count the facts they use into one resolution, `to` with
+        // the opposite sign, so each fact's uncertainty counts once.
+        let mut resolution = Resolution::default();
+        let (from_factor, from_dimension) = self.resolve_expr(from, policy, &mut resolution, 1)?;
+        let (to_factor, to_dimension) = self.resolve_expr(to, policy, &mut resolution, -1)?;
+        if from_dimension != to_dimension {
+            return Err(ConversionError::IncompatibleDimensions {
+                from: from.to_string(),
+                to: to.to_string(),
+                from_dimension,
+                to_dimension,
+            });
+        }
+
+        if let (Some(from), Some(to)) = (from.as_simple(), to.as_simple()) {
+            if let Some(converted) = self.convert_along(value.clone(), from, to, policy) {
+                return Ok(converted);
+            }
+        }
+        // Absolute temperatures can only be converted along facts, since their
+        // offsets don't reduce to a base unit.
+        let absolute = |expr: &UnitExpr| self.is_absolute_temperature(expr);
+        if absolute(from) || absolute(to) {
+            let (absolute, other) = if absolute(from) {
+                (from, to)
+            } else {
+                (to, from)
+            };
+            let is_difference = other
+                .as_simple()
+                .and_then(|name| self.lookup_id(name).ok())
+                .is_some_and(|unit| unit.difference);
+            if is_difference {
+                return Err(ConversionError::AbsoluteAndDifference {
+                    absolute: absolute.to_string(),
+                    difference: other.to_string(),
+                });
+            }
+            return Err(ConversionError::NoPath {
+                from: from.to_string(),
+                to: to.to_string(),
+            });
+        }
+        Ok(value * from_factor / to_factor * resolution.uncertainty())
+    }
+
+    /// Converts like [`UnitGraph::convert_expr`] and shows how: each ratio
+    /// or offset applied, the value after it, and the facts it came from.
+    pub fn explain(
+        &self,
+        value: f64,
+        from: &UnitExpr,
+        to: &UnitExpr,
+    ) -> Result<Explanation, ConversionError> {
+        self.explain_by(value, from, to, RoutePolicy::default())
+    }
+
+    /// Explains like [`UnitGraph::explain`] for a conversion by `policy`,
+    /// also listing the other chains of facts between two plain units that
+    /// the policy passed over, best first.
+    pub fn explain_by(
+        &self,
+        value: f64,
+        from: &UnitExpr,
+        to: &UnitExpr,
+        policy: RoutePolicy,
+    ) -> Result<Explanation, ConversionError> {
+        let result = self.convert_expr_by(value, from, to, policy)?;
+        let along_path = match (from.as_simple(), to.as_simple()) {
+            (Some(from), Some(to)) => self.explain_path(value, from, to, policy),
+            _ => None,
+        };
+        let (steps, rejected) = match along_path {
+            Some(explained) => explained,
+            None => (self.explain_bases(value, from, to, policy)?, Vec::new()),
+        };
+        Ok(Explanation {
+            value,
+            from: from.to_string(),
+            steps,
+            result,
+            to: to.to_string(),
+            policy,
+            rejected,
+        })
+    }
+
+    /// The unit of `system` that writes `value` of `unit` most readably, and
+    /// the value in it: the smallest value no less than 1, or the largest if
+    /// every unit gives a value below 1. 100000 in is 1.578 mi in `imperial`
+    /// and 2.541 km in `metric`.
+    ///
+    /// The candidates are the plain units declared `system=<system>` that
+    /// measure the same dimension, plus power-of-1000 SI prefixes on the
+    /// smallest and largest of them where they accept prefixes, so `metric`
+    /// reaches `km` and `ms` but never `ks` next to `hr`. Absolute
+    /// temperatures are never candidates.
+    pub fn to_best(
+        &self,
+        value: f64,
+        unit: &UnitExpr,
+        system: &str,
+    ) -> Result<(f64, UnitExpr), ConversionError> {
+        let system = self.system(system)?;
+        let dimension = self.dimension(unit)?;
+        let mut sizes = Vec::new();
+        for (id, symbol) in self.units.iter().enumerate() {
+            let candidate = UnitExpr::unit(symbol);
+            if !self.in_system(id, system)
+                || self.affine.contains(&id)
+                || self.dimension(&candidate).ok() != Some(dimension.clone())
+            {
+                continue;
+            }
+            sizes.push((id, self.convert_expr(1., &candidate, unit)?));
+        }
+        let smallest = sizes
+            .iter()
+            .map(|&(_, size)| size)
+            .fold(f64::INFINITY, f64::min);
+        let largest = sizes.iter().map(|&(_, size)| size).fold(0., f64::max);
+
+        let mut candidates: Vec<UnitExpr> = Vec::new();
+        for &(id, size) in &sizes {
+            candidates.push(UnitExpr::unit(&self.units[id]));
+            let accepts_si = self
+                .prefixes
+                .get(&id)
+                .is_some_and(|systems| systems.contains(&PrefixSystem::Si));
+            if !accepts_si {
+                continue;
+            }
+            let mut seen = Vec::new();
+            for prefix in prefix::PREFIXES.iter() {
+                let power = prefix.factor.log10().round() as i32;
+                let extends = if power > 0 {
+                    size == largest
+                } else {
+                    size == smallest
+                };
+                // Micro has three symbols; only the first is written.
+                if prefix.system != PrefixSystem::Si
+                    || power % 3 != 0
+                    || !extends
+                    || seen.contains(&prefix.name)
+                {
+                    continue;
+                }
+                seen.push(prefix.name);
+                candidates.push(UnitExpr::unit(&format!(
+                    "{}{}",
+                    prefix.symbol, self.units[id]
+                )));
+            }
+        }
+
+        // Closer to 1 from above is better, and anything from above beats
+        // anything below.
+        let readable = |a: f64, b: f64| match (a >= 1., b >= 1.) {
+            (true, true) => a < b,
+            (true, false) => true,
+            (false, true) => false,
+            (false, false) => a > b,
+        };
+        let mut best: Option<(f64, UnitExpr)> = None;
+        for candidate in candidates {
+            let Ok(converted) = self.convert_expr(value, unit, &candidate) else {
+                continue;
+            };
+            if best
+                .as_ref()
+                .is_none_or(|(other, _)| readable(converted.abs(), other.abs()))
+            {
+                best = Some((converted, candidate));
+            }
+        }
+        best.ok_or_else(|| ConversionError::NoUnitInSystem {
+            system: system.to_string(),
+            dimension,
+        })
+    }
+
+    /// Splits `value` of `unit` across `parts`: a whole number of each part
+    /// but the last, which takes what is left, so 71 in over `ft, in` is
+    /// 5 ft and 11 in. The split is worked out with exact ratios, so the
+    /// parts add back up to `value`. Negative values give negative parts.
+    pub fn breakdown(
+        &self,
+        value: &Number,
+        unit: &UnitExpr,
+        parts: &[UnitExpr],
+    ) -> Result<Vec<(f64, UnitExpr)>, ConversionError> {
+        let to_f64 = |amount: &BigRational| amount.to_f64().unwrap_or(f64::NAN);
+        let Some((last, wholes)) = parts.split_last() else {
+            return Ok(Vec::new());
+        };
+        let mut rest = BigRational::of(value);
+        let mut current = unit;
+        let mut split = Vec::new();
+        for part in wholes {
+            let amount = self.convert_expr(rest, current, part)?;
+            let whole = amount.trunc();
+            split.push((to_f64(&whole), part.clone()));
+            rest = amount - whole;
+            current = part;
+        }
+        let amount = self.convert_expr(rest, current, last)?;
+        split.push((to_f64(&amount), last.clone()));
+        Ok(split)
+    }
+
+    /// Checks the cycles of facts and returns the ones whose ratios or
+    /// offsets disagree by more than `tolerance`, relative to the stated
+    /// factor, along with units defined by several compound facts that
+    /// disagree.
+    ///
+    /// Each cycle that visits no unit twice is checked once, as closed by its
+    /// last fact, so a later fact contradicting earlier ones is the one
+    /// reported, with the cycle that disagrees most. Small errors compound
+    /// around a cycle, so it may be out of tolerance even when every shorter
+    /// cycle it is made of is within it. A fact closing more than
+    /// [`MAX_CYCLES`] cycles only has the first of them checked.
+    pub fn inconsistencies(&self, tolerance: f64) -> Vec<Inconsistency> {
+        let in_tree = self.tree_facts();
+        let mut found = Vec::new();
+        for (id, fact) in self.facts.iter().enumerate() {
+            if in_tree[id] {
+                continue;
+            }
+            let link = &self.links[id];
+            let earlier = |edge: &Edge| edge.fact < id;
+            let worst = self
+                .routes_where(link.from, link.to, MAX_CYCLES, &earlier)
+                .iter()
+                .map(|path| self.cycle(path, fact, link))
+                .max_by(|a, b| a.relative_error.total_cmp(&b.relative_error));
+            found.extend(worst.filter(|cycle| cycle.relative_error > tolerance));
+        }
+        found.extend(self.conflicting_definitions(tolerance));
+        found
+    }
+
+    // The fact `link` checked against the chain of facts `path` between its
+    // units. Offsets are compared relative to the larger of the stated factor
+    // and offset.
+    fn cycle(&self, path: &[Edge], fact: &Fact, link: &Link) -> Inconsistency {
+        let along = |value: f64| {
+            path.iter()
+                .fold(value, |acc, edge| self.step(edge, acc, true))
+        };
+        let implied_offset = along(0.);
+        let implied = along(1.) - implied_offset;
+        let (stated, offset) = (link.factor.value(), link.offset.value());
+        let relative_error = ((implied - stated) / stated)
+            .abs()
+            .max((implied_offset - offset).abs() / stated.abs().max(offset.abs()));
+        let mut facts: Vec<Fact> = path
+            .iter()
+            .map(|edge| self.facts[edge.fact].clone())
+            .collect();
+        facts.push(fact.clone());
+        // Compare the unprefixed units, then report in the fact's own units.
+        let to_prefix = self.lookup_id(&fact.to).map_or(1., |unit| unit.scale());
+        Inconsistency {
+            facts,
+            implied: implied * fact.factor.value() / stated,
+            stated: fact.factor.value(),
+            implied_offset: implied_offset / to_prefix,
+            stated_offset: fact.offset.value(),
+            relative_error,
+        }
+    }
+
+    // Later compound facts defining a unit that an earlier one already
+    // defines, where the two disagree. Only the first is used to convert.
+    fn conflicting_definitions(&self, tolerance: f64) -> Vec<Inconsistency> {
+        let base = |definition: &Definition| {
+            self.resolve_expr::<f64>(
+                &definition.expr,
+                RoutePolicy::default(),
+                &mut Resolution::default(),
+                1,
+            )
+            .ok()
+        };
+        let mut found = Vec::new();
+        for (i, second) in self.definitions.iter().enumerate() {
+            let Some(first) = self.definitions[..i].iter().find(|d| d.unit == second.unit) else {
+                continue;
+            };
+            let (Some((first_factor, first_dimension)), Some((second_factor, second_dimension))) =
+                (base(first), base(second))
+            else {
+                continue;
+            };
+            // How many of the second fact's right-hand side the first implies.
+            let stated = second.factor.value();
+            let (implied, relative_error) = if first_dimension == second_dimension {
+                let implied = first.factor.value() * first_factor / second_factor;
+                (implied, ((implied - stated) / stated).abs())
+            } else {
+                (f64::NAN, f64::INFINITY)
+            };
+            if relative_error > tolerance {
+                found.push(Inconsistency {
+                    facts: vec![first.fact.clone(), second.fact.clone()],
+                    implied: implied * second.fact.factor.value() / stated,
+                    stated: second.fact.factor.value(),
+                    implied_offset: 0.,
+                    stated_offset: 0.,
+                    relative_error,
+                });
+            }
+        }
+        found
+    }
+
+    /// Compiles the graph into a table of every unit's ratio to the base unit
+    /// of its dimension, for converting the same units many times over. See
+    /// [`CompiledGraph`].
+    pub fn compile(&self) -> Result<CompiledGraph, ConversionError> {
+        let policy = RoutePolicy::default();
+        let mut compiled = CompiledGraph::default();
+        // Each unit's names, and whether they match in any case.
+        let mut names: Vec<Vec<(&String, bool)>> = vec![Vec::new(); self.units.len()];
+        for (name, &unit) in self.index.iter().chain(&self.aliases) {
+            names[unit].push((name, false));
+        }
+        for (name, &unit) in &self.folded {
+            names[unit].push((name, true));
+        }
+        for (unit, names) in names.iter().enumerate() {
+            let (scale, dimension) =
+                self.resolve_unit::<f64>(unit, policy, &mut Resolution::default(), 1)?;
+            let dimension = compiled.intern(dimension);
+            let symbol = &self.units[unit];
+            let absolute = self.affine.contains(&unit);
+            // Where an absolute temperature's zero is, in the base unit.
+            let offset = if absolute {
+                let anchor = self.anchor(unit)?;
+                let path = self.path(unit, anchor, policy).unwrap_or_default();
+                let zero = path.iter().fold(0., |acc, edge| self.step(edge, acc, true));
+                zero * self
+                    .resolve_unit::<f64>(anchor, policy, &mut Resolution::default(), 1)?
+                    .0
+            } else {
+                0.
+            };
+            let entry = |prefix: Option<&Prefix>, difference: bool| Entry {
+                symbol: format!(
+                    "{}{}{}",
+                    if difference { "Δ" } else { "" },
+                    prefix.map_or("", |prefix| prefix.symbol),
+                    symbol
+                ),
+                dimension,
+                scale: scale * prefix_factor(prefix),
+                offset: if difference { 0. } else { offset },
+                absolute: absolute && !difference,
+                difference,
+            };
+
+            let id = compiled.push(entry(None, false));
+            for &(name, folded) in names {
+                compiled.name(name.clone(), id, folded);
+            }
+            if absolute {
+                let difference = compiled.push(entry(None, true));
+                for (name, _) in names {
+                    compiled.name(format!("Δ{}", name), difference, false);
+                    compiled.name(format!("delta_{}", name), difference, false);
+                }
+            }
+
+            // Prefixed forms, each checked against how the graph reads it
+            // since a prefixed name can be taken by another unit.
+            let Some(systems) = self.prefixes.get(&unit) else {
+                continue;
+            };
+            let reads_as = |name: &str, prefix: &Prefix| {
+                self.lookup_id(name).is_ok_and(|found| {
+                    found.unit == unit && found.prefix.is_some_and(|p| p.name == prefix.name)
+                })
+            };
+            let mut prefixed: HashMap<&str, UnitId> = HashMap::new();
+            for prefix in prefix::PREFIXES
+                .iter()
+                .filter(|prefix| systems.contains(&prefix.system))
+            {
+                // Micro has several symbols but is one row.
+                let id = *prefixed
+                    .entry(prefix.name)
+                    .or_insert_with(|| compiled.push(entry(Some(prefix), false)));
+                let by_symbol = format!("{}{}", prefix.symbol, symbol);
+                if reads_as(&by_symbol, prefix) {
+                    compiled.name(by_symbol, id, false);
+                }
+                if let Some((name, plural)) = self.names.get(&unit) {
+                    for name in [name, plural] {
+                        let by_name = format!("{}{}", prefix.name, name);
+                        if reads_as(&by_name, prefix) {
+                            compiled.name(by_name, id, true);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(compiled)
+    }
+
+    // Reduces a unit expression to a factor and a dimension: one `expr` is
+    // `factor` of the declared or defining units its dimension is made of.
+    // The factor is computed without uncertainty; the measured facts it uses
+    // are counted into `resolution`, `power` times each.
+    fn resolve_expr<T: Scalar>(
+        &self,
+        expr: &UnitExpr,
+        policy: RoutePolicy,
+        resolution: &mut Resolution,
+        power: i32,
+    ) -> Result<(T, Dimension), ConversionError> {
+        let mut factor = T::one();
+        let mut dimension = Dimension::dimensionless();
+        let out_of_range = || ConversionError::ParseError {
+            input: expr.to_string(),
+            position: 0,
+            reason: "the power is out of range".to_string(),
+        };
+        for (symbol, exponent) in expr.terms() {
+            let unit = self.lookup_id(symbol)?;
+            if expr.as_simple().is_none() && self.is_absolute(&unit) {
+                return Err(ConversionError::AffineInCompound {
+                    unit: symbol.clone(),
+                    expression: expr.to_string(),
+                });
+            }
+            // Definitions nest, so a power can grow past what any one
+            // expression writes; allow one level of that.
+            let total = power
+                .checked_mul(*exponent)
+                .filter(|total| total.abs() <= MAX_POWER * MAX_POWER)
+                .ok_or_else(out_of_range)?;
+            let (unit_factor, unit_dimension) =
+                self.resolve_unit::<T>(unit.unit, policy, resolution, total)?;
+            let prefix = T::of(&Number::from(unit.scale()));
+            factor = factor * (prefix * unit_factor).powi(*exponent);
+            dimension = unit_dimension
+                .checked_powi(*exponent)
+                .and_then(|unit_dimension| dimension.checked_mul(&unit_dimension))
+                .ok_or_else(out_of_range)?;
+        }
+        Ok((factor, dimension))
+    }
+
+    fn resolve_unit<T: Scalar>(
+        &self,
+        unit: usize,
+        policy: RoutePolicy,
+        resolution: &mut Resolution,
+        power: i32,
+    ) -> Result<(T, Dimension), ConversionError> {
+        let anchor = self.anchor(unit)?;
+        let ratio = self.ratio(unit, anchor, policy, resolution, power);
+        if let Some(dimension) = self.dimensions.get(&anchor) {
+            return Ok((ratio, Dimension::base(dimension)));
+        }
+        let Some((index, definition)) = self
+            .definitions
+            .iter()
+            .enumerate()
+            .find(|(_, d)| d.unit == anchor)
+        else {
+            return Ok((ratio, Dimension::base(&self.units[anchor])));
+        };
+        if resolution.visiting.contains(&anchor) {
+            return Err(ConversionError::CircularDefinition {
+                fact: Box::new(definition.fact.clone()),
+            });
+        }
+        resolution.visiting.push(anchor);
+        let (factor, dimension) =
+            self.resolve_expr::<T>(&definition.expr, policy, resolution, power)?;
+        resolution.visiting.pop();
+        resolution.measure(
+            Measured::Definition(index),
+            power,
+            definition.fact.relative_uncertainty(),
+        );
+        Ok((ratio * T::of(&definition.factor) * factor, dimension))
+    }
+
+    // The steps `convert` takes along the path between two plain units, in the
+    // same order so the values match, and the routes `policy` passed over, or
+    // `None` where `convert` has no answer.
+    fn explain_path(
+        &self,
+        value: f64,
+        from: &str,
+        to: &str,
+        policy: RoutePolicy,
+    ) -> Option<(Vec<Step>, Vec<Route>)> {
+        let from = self.lookup_id(from).ok()?;
+        let to = self.lookup_id(to).ok()?;
+        let offsets = self.is_absolute(&from) || self.is_absolute(&to);
+        if offsets && (from.difference || to.difference) {
+            return None;
+        }
+        let path = self.path(from.unit, to.unit, policy)?;
+        let facts_of = |path: &[Edge]| -> Vec<(usize, bool)> {
+            path.iter().map(|edge| (edge.fact, edge.inverse)).collect()
+        };
+        let mut rejected: Vec<Route> = self
+            .routes(from.unit, to.unit, MAX_ROUTES)
+            .into_iter()
+            .filter(|route| facts_of(route) != facts_of(&path))
+            .map(|route| self.route(&route))
+            .collect();
+        rejected.sort_by(|a, b| {
+            a.cost(policy)
+                .partial_cmp(&b.cost(policy))
+                .unwrap_or(Ordering::Equal)
+        });
+        let named = |unit: usize, prefix: Option<&Prefix>| {
+            let difference = from.difference && self.affine.contains(&unit);
+            format!(
+                "{}{}{}",
+                if difference { "Δ" } else { "" },
+                prefix.map_or("", |prefix| prefix.symbol),
+                self.units[unit]
+            )
+        };
+        let mut steps = Vec::new();
+        let mut value = value;
+        let mut push = |operation: String, value: f64, unit: String, facts: Vec<Fact>| {
+            steps.push(Step {
+                operation,
+                value,
+                unit,
+                facts,
+            })
+        };
+
+        // Mirrors `convert`: offsets are in unprefixed units, otherwise both
+        // prefixes are folded into one scale up front and the path is walked
+        // in units carrying the target's prefix.
+        let label_prefix = if offsets { None } else { to.prefix };
+        let label = |unit: usize| named(unit, label_prefix);
+        if offsets {
+            if let Some(prefix) = from.prefix {
+                value *= prefix.factor;
+                let unit = named(from.unit, None);
+                let operation = format!(
+                    "× {} {}/{}",
+                    prefix.factor,
+                    unit,
+                    named(from.unit, Some(prefix))
+                );
+                push(operation, value, unit, Vec::new());
+            }
+        } else {
+            let one = Number::from(1.);
+            let from_prefix = Number::from(from.scale());
+            let scale = scaled(&one, &from_prefix, &Number::from(to.scale()));
+            if scale.value() != 1. {
+                value *= scale.value();
+                let unit = label(from.unit);
+                let operation = format!("× {} {}/{}", scale, unit, named(from.unit, from.prefix));
+                push(operation, value, unit, Vec::new());
+            }
+        }
+        for edge in &path {
+            let link = &self.links[edge.fact];
+            let fact = self.facts[edge.fact].clone();
+            let ratio = format!("{} {}/{}", link.factor, label(link.to), label(link.from));
+            let factor = link.factor.value();
+            let offset = link.offset.value();
+            let (sign, magnitude) = if offset < 0. {
+                ("−", -offset)
+            } else {
+                ("+", offset)
+            };
+            if !offsets || offset == 0. {
+                let operation = if edge.inverse {
+                    value /= factor;
+                    format!("÷ {}", ratio)
+                } else {
+                    value *= factor;
+                    format!("× {}", ratio)
+                };
+                push(operation, value, label(edge.to), vec![fact]);
+            } else if edge.inverse {
+                let unsign = if sign == "+" { "−" } else { "+" };
+                value -= offset;
+                let operation = format!("{} {} {}", unsign, magnitude, label(link.to));
+                push(operation, value, label(link.to), vec![fact.clone()]);
+                value /= factor;
+                push(format!("÷ {}", ratio), value, label(link.from), vec![fact]);
+            } else {
+                value *= factor;
+                push(
+                    format!("× {}", ratio),
+                    value,
+                    label(link.to),
+                    vec![fact.clone()],
+                );
+                value += offset;
+                let operation = format!("{} {} {}", sign, magnitude, label(link.to));
+                push(operation, value, label(link.to), vec![fact]);
+            }
+        }
+        if offsets {
+            if let Some(prefix) = to.prefix {
+                value /= prefix.factor;
+                let unit = named(to.unit, Some(prefix));
+                let operation = format!("÷ {} {}/{}", prefix.factor, named(to.unit, None), unit);
+                push(operation, value, unit, Vec::new());
+            }
+        }
+        Some((steps, rejected))
+    }
+
+    // The two steps of a conversion through base units: into the units the
+    // dimension is made of, then out of them.
+    fn explain_bases(
+        &self,
+        value: f64,
+        from: &UnitExpr,
+        to: &UnitExpr,
+        policy: RoutePolicy,
+    ) -> Result<Vec<Step>, ConversionError> {
+        let mut resolution = Resolution::default();
+        let (from_factor, _) = self.resolve_expr::<f64>(from, policy, &mut resolution, 1)?;
+        let (to_factor, _) = self.resolve_expr::<f64>(to, policy, &mut resolution, -1)?;
+        let mut base = Vec::new();
+        let mut from_facts = Vec::new();
+        self.reduce(from, 1, policy, &mut base, &mut from_facts)?;
+        let base = UnitExpr::from_terms(base)?.to_string();
+        let mut to_facts = Vec::new();
+        self.reduce(to, 1, policy, &mut Vec::new(), &mut to_facts)?;
+
+        let into_base = value * from_factor;
+        let steps = [
+            Step {
+                operation: format!("× {} {} per {}", from_factor, base, from),
+                value: into_base,
+                unit: base.clone(),
+                facts: from_facts,
+            },
+            Step {
+                operation: format!("÷ {} {} per {}", to_factor, base, to),
+                value: into_base / to_factor,
+                unit: to.to_string(),
+                facts: to_facts,
+            },
+        ];
+        // A unit that is its own base (`m/s` above) adds nothing to the chain.
+        let factors = [from_factor, to_factor];
+        Ok(steps
+            .into_iter()
+            .zip(factors)
+            .filter(|(step, factor)| *factor != 1. || !step.facts.is_empty())
+            .map(|(step, _)| step)
+            .collect())
+    }
+
+    // Rewrites `expr` (raised to `power`) in the units its dimension is made
+    // of, collecting the facts that takes. Only called once `resolve_expr` has
+    // checked the expression, so definitions don't loop.
+    fn reduce(
+        &self,
+        expr: &UnitExpr,
+        power: i32,
+        policy: RoutePolicy,
+        base: &mut Vec<(String, i32)>,
+        facts: &mut Vec<Fact>,
+    ) -> Result<(), ConversionError> {
+        for (symbol, exponent) in expr.terms() {
+            let unit = self.lookup_id(symbol)?.unit;
+            let anchor = self.anchor(unit)?;
+            let mut cited: Vec<&Fact> = self
+                .path(unit, anchor, policy)
+                .unwrap_or_default()
+                .iter()
+                .map(|edge| &self.facts[edge.fact])
+                .collect();
+            let definition = self.definitions.iter().find(|d| d.unit == anchor);
+            cited.extend(definition.map(|d| &d.fact));
+            for fact in cited {
+                if !facts.contains(fact) {
+                    facts.push(fact.clone());
+                }
+            }
+            match definition {
+                Some(definition) => {
+                    self.reduce(&definition.expr, power * exponent, policy, base, facts)?
+                }
+                None => base.push((self.units[anchor].clone(), power * exponent)),
+            }
+        }
+        Ok(())
+    }
+
+    fn in_system(&self, unit: usize, system: &str) -> bool {
+        self.systems
+            .get(&unit)
+            .is_some_and(|systems| systems.iter().any(|s| s == system))
+    }
+
+    // The names `unit` is known by, symbol first.
+    fn forms(&self, unit: usize) -> Vec<String> {
+        let symbol = &self.units[unit];
+        let mut forms = vec![symbol.clone()];
+        if self.affine.contains(&unit) {
+            forms.push(format!("Δ{}", symbol));
+        }
+        if let Some((name, plural)) = self.names.get(&unit) {
+            forms.extend([name.clone(), plural.clone()]);
+        }
+        forms.extend(
+            self.aliases
+                .iter()
+                .filter(|&(_, &u)| u == unit)
+                .map(|(alias, _)| alias.clone()),
+        );
+        forms
+    }
+
+    // The unit that sets the dimension of everything connected to `unit`: the
+    // one declared with a dimension or defined by a compound fact, or the
+    // first unit of the group if there is neither.
+    fn anchor(&self, unit: usize) -> Result<usize, ConversionError> {
+        self.anchors.get_or_init(|| self.find_anchors())[unit].clone()
+    }
+
+    // The anchor of every unit, one connected group at a time.
+    fn find_anchors(&self) -> Vec<Result<usize, ConversionError>> {
+        let defined: HashSet<usize> = self.definitions.iter().map(|d| d.unit).collect();
+        let mut seen = vec![false; self.units.len()];
+        let mut anchors = vec![Ok(0); self.units.len()];
+        for start in 0..self.units.len() {
+            if seen[start] {
+                continue;
+            }
+            // Every unit connected to `start` by facts, including itself.
+            let mut component = vec![start];
+            seen[start] = true;
+            let mut i = 0;
+            while i < component.len() {
+                for edge in &self.edges[component[i]] {
+                    if !seen[edge.to] {
+                        seen[edge.to] = true;
+                        component.push(edge.to);
+                    }
+                }
+                i += 1;
+            }
+            let mut candidates = component
+                .iter()
+                .copied()
+                .filter(|u| self.dimensions.contains_key(u) || defined.contains(u));
+            let anchor = match (candidates.next(), candidates.next()) {
+                (Some(first), Some(second)) => Err(ConversionError::ConflictingDimensions {
+                    first: self.units[first].clone(),
+                    second: self.units[second].clone(),
+                }),
+                (Some(anchor), None) => Ok(anchor),
+                (None, _) => Ok(start),
+            };
+            for unit in component {
+                anchors[unit] = anchor.clone();
+            }
+        }
+        anchors
+    }
+
+    fn lookup_id(&self, name: &str) -> Result<UnitRef, ConversionError> {
+        let plain = |unit, prefix| UnitRef {
+            unit,
+            prefix,
+            difference: false,
+        };
+        if let Some(unit) = self.find(name) {
+            return Ok(plain(unit, None));
+        }
+        if let Some(unit) = self.difference_of(name) {
+            return Ok(UnitRef {
+                difference: true,
+                ..plain(unit, None)
+            });
+        }
+        match self.prefixed_readings(name).as_slice() {
+            [(prefix, unit)] => Ok(plain(*unit, Some(*prefix))),
+            [] => Err(self.unknown_unit(name)),
+            readings => Err(ConversionError::AmbiguousUnit {
+                name: name.to_string(),
+                candidates: readings
+                    .iter()
+                    .map(|(prefix, unit)| format!("{}-{}", prefix.name, self.units[*unit]))
+                    .collect(),
+            }),
+        }
+    }
+
+    // The unit a name refers to without prefixes: its symbol, an alias, or a
+    // full name or case-insensitive symbol in any case.
+    fn find(&self, name: &str) -> Option<usize> {
+        self.index
+            .get(name)
+            .or_else(|| self.aliases.get(name))
+            .or_else(|| self.folded.get(&name.to_lowercase()))
+            .copied()
+    }
+
+    // Takes `name` for `unit`, unless another unit already goes by it.
+    fn claim(&self, name: &str, unit: usize) -> Result<(), ConversionError> {
+        match self.index.get(name).or_else(|| self.aliases.get(name)) {
+            Some(&other) if other != unit => Err(self.duplicate(name, other, unit)),
+            _ => Ok(()),
+        }
+    }
+
+    fn duplicate(&self, name: &str, first: usize, second: usize) -> ConversionError {
+        ConversionError::DuplicateName {
+            name: name.to_string(),
+            first: self.units[first].clone(),
+            second: self.units[second].clone(),
+        }
+    }
+
+    // The affine unit `ΔdegC` or `delta_degC` is a difference of.
+    fn difference_of(&self, name: &str) -> Option<usize> {
+        let unit = name
+            .strip_prefix('Δ')
+            .or_else(|| name.strip_prefix("delta_"))?;
+        self.find(unit).filter(|unit| self.affine.contains(unit))
+    }
+
+    fn is_absolute(&self, unit: &UnitRef) -> bool {
+        !unit.difference && self.affine.contains(&unit.unit)
+    }
+
+    // Every way to read `name` as a prefix on a unit that accepts it: a
+    // prefix symbol on a symbol (`km`), or a prefix name on a full name in any
+    // case (`Kilometers`).
+    fn prefixed_readings(&self, name: &str) -> Vec<(&'static Prefix, usize)> {
+        let by_symbol = prefix::split_prefix(name)
+            .filter_map(|(prefix, rest)| Some((prefix, *self.index.get(rest)?)));
+        let lower = name.to_lowercase();
+        let by_name = prefix::PREFIXES.iter().filter_map(|prefix| {
+            let rest = lower.strip_prefix(prefix.name)?;
+            let unit = *self.folded.get(rest)?;
+            let (singular, plural) = self.names.get(&unit)?;
+            (singular.to_lowercase() == rest || plural.to_lowercase() == rest)
+                .then_some((prefix, unit))
+        });
+        let mut readings: Vec<(&'static Prefix, usize)> = Vec::new();
+        for (prefix, unit) in by_symbol.chain(by_name) {
+            let accepts = self
+                .prefixes
+                .get(&unit)
+                .is_some_and(|systems| systems.contains(&prefix.system));
+            // Micro has several symbols but is one reading.
+            let seen = readings
+                .iter()
+                .any(|(p, u)| p.name == prefix.name && *u == unit);
+            if accepts && !seen {
+                readings.push((prefix, unit));
+            }
+        }
+        readings
+    }
+
+    fn intern_or_prefixed(&mut self, name: &str) -> (usize, Option<&'static Prefix>) {
+        if let Some(unit) = self.find(name) {
+            return (unit, None);
+        }
+        if let [(prefix, unit)] = self.prefixed_readings(name).as_slice() {
+            return (*unit, Some(*prefix));
+        }
+        (self.intern(name), None)
+    }
+
+    // A unit that wasn't declared with a `unit` line but whose name also reads
+    // as a prefixed unit, like `min` once `in` takes SI prefixes, can't be told
+    // apart from the prefixed unit. Declaring it settles it.
+    fn check_prefix_collisions(&self) -> Result<(), ConversionError> {
+        for (unit, name) in self.units.iter().enumerate() {
+            if self.declared.contains(&unit) {
+                continue;
+            }
+            if let Some((prefix, prefixed)) = self.prefixed_readings(name).first() {
+                return Err(ConversionError::PrefixCollision {
+                    name: name.clone(),
+                    prefix: prefix.name.to_string(),
+                    unit: self.units[*prefixed].clone(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    // How many `to` there are in one `from`, for two connected units,
+    // counting the measured facts on the way into `resolution`.
+    fn ratio<T: Scalar>(
+        &self,
+        from: usize,
+        to: usize,
+        policy: RoutePolicy,
+        resolution: &mut Resolution,
+        power: i32,
+    ) -> T {
+        let path = self.path(from, to, policy).unwrap_or_default();
+        path.iter().fold(T::one(), |acc, edge| {
+            let link = &self.links[edge.fact];
+            let (value, power) = if edge.inverse {
+                (acc / T::of(&link.factor), -power)
+            } else {
+                (acc * T::of(&link.factor), power)
+            };
+            resolution.measure(Measured::Link(edge.fact), power, link.relative_uncertainty);
+            value
+        })
+    }
+
+    // Walks one edge: forwards multiplies by the factor and adds the offset,
+    // backwards undoes that.
+    fn step<T: Scalar>(&self, edge: &Edge, value: T, offsets: bool) -> T {
+        let link = &self.links[edge.fact];
+        let factor = T::measured(&link.factor, link.relative_uncertainty);
+        if !offsets || link.offset.value() == 0. {
+            return if edge.inverse {
+                value / factor
+            } else {
+                value * factor
+            };
+        }
+        let offset = T::of(&link.offset);
+        if edge.inverse {
+            (value - offset) / factor
+        } else {
+            value * factor + offset
+        }
+    }
+
+    // Which facts make up a spanning forest grown in the order the facts
+    // were added. Every other fact closes at least one cycle.
+    fn tree_facts(&self) -> Vec<bool> {
+        // Union-find over the facts in order picks the tree edges.
+        let mut component: Vec<usize> = (0..self.units.len()).collect();
+        fn find(component: &mut [usize], mut u: usize) -> usize {
+            while component[u] != u {
+                component[u] = component[component[u]];
+                u = component[u];
+            }
+            u
+        }
+        let mut in_tree = vec![false; self.facts.len()];
+        for (id, link) in self.links.iter().enumerate() {
+            let from = find(&mut component, link.from);
+            let to = find(&mut component, link.to);
+            if from != to {
+                component[from] = to;
+                in_tree[id] = true;
+            }
+        }
+        in_tree
+    }
+
+    fn intern(&mut self, unit: &str) -> usize {
+        if let Some(&id) = self.index.get(unit) {
+            return id;
+        }
+        self.anchors.take();
+        let id = self.units.len();
+        self.index.insert(unit.to_string(), id);
+        self.units.push(unit.to_string());
+        self.edges.push(Vec::new());
+        id
+    }
+
+    // The chain of edges from one unit to another that `policy` prefers. The
+    // fewest facts are found breadth first, in the order the facts were
+    // added; other policies weigh each fact and take the cheapest chain.
+    fn path(&self, from: usize, to: usize, policy: RoutePolicy) -> Option<Vec<Edge>> {
+        let mut came_from: Vec<Option<(usize, Edge)>> = vec![None; self.units.len()];
+        if policy == RoutePolicy::FewestHops {
+            let mut queue = VecDeque::from([from]);
+            while let Some(node) = queue.pop_front() {
+                if node == to {
+                    break;
+                }
+                for edge in &self.edges[node] {
+                    if edge.to != from && came_from[edge.to].is_none() {
+                        came_from[edge.to] = Some((node, *edge));
+                        queue.push_back(edge.to);
+                    }
+                }
+            }
+        } else {
+            // Dijkstra's algorithm over (hops, measured facts, variance).
+            let mut costs: Vec<Option<(usize, usize, f64)>> = vec![None; self.units.len()];
+            let mut done = vec![false; self.units.len()];
+            costs[from] = Some((0, 0, 0.));
+            let key = |(hops, measured, variance): (usize, usize, f64)| {
+                policy.cost(hops, measured, variance)
+            };
+            while let Some((node, (hops, measured, variance))) = costs
+                .iter()
+                .enumerate()
+                .filter(|(node, _)| !done[*node])
+                .filter_map(|(node, cost)| Some((node, (*cost)?)))
+                .min_by(|a, b| key(a.1).partial_cmp(&key(b.1)).unwrap_or(Ordering::Equal))
+            {
+                if node == to {
+                    break;
+                }
+                done[node] = true;
+                for edge in &self.edges[node] {
+                    let relative = self.links[edge.fact].relative_uncertainty;
+                    let cost = (
+                        hops + 1,
+                        measured + usize::from(relative > 0.),
+                        variance + relative * relative,
+                    );
+                    let cheaper = costs[edge.to].is_none_or(|other| key(cost) < key(other));
+                    if !done[edge.to] && cheaper {
+                        costs[edge.to] = Some(cost);
+                        came_from[edge.to] = Some((node, *edge));
+                    }
+                }
+            }
+        }
+
+        let mut path = Vec::new();
+        let mut node = to;
+        while node != from {
+            let (prev, edge) = came_from[node]?;
+            path.push(edge);
+            node = prev;
+        }
+        path.reverse();
+        Some(path)
+    }
+
+    // Every chain of facts between two units that visits no unit twice, up to
+    // `limit` of them, in the order a depth-first search finds them.
+    fn routes(&self, from: usize, to: usize, limit: usize) -> Vec<Vec<Edge>> {
+        self.routes_where(from, to, limit, &|_| true)
+    }
+
+    // Like `routes`, only taking the edges `allowed` accepts, and giving up
+    // after `MAX_SEARCH_STEPS` steps.
+    fn routes_where(
+        &self,
+        from: usize,
+        to: usize,
+        limit: usize,
+        allowed: &dyn Fn(&Edge) -> bool,
+    ) -> Vec<Vec<Edge>> {
+        struct Search<'a> {
+            graph: &'a UnitGraph,
+            to: usize,
+            limit: usize,
+            allowed: &'a dyn Fn(&Edge) -> bool,
+            steps: usize,
+            path: Vec<Edge>,
+            visited: Vec<usize>,
+            found: Vec<Vec<Edge>>,
+        }
+        fn walk(search: &mut Search, node: usize) {
+            if node == search.to {
+                search.found.push(search.path.clone());
+                return;
+            }
+            for edge in &search.graph.edges[node] {
+                if search.found.len() >= search.limit || search.steps >= MAX_SEARCH_STEPS {
+                    return;
+                }
+                if search.visited.contains(&edge.to) || !(search.allowed)(edge) {
+                    continue;
+                }
+                search.steps += 1;
+                search.path.push(*edge);
+                search.visited.push(edge.to);
+                walk(search, edge.to);
+                search.visited.pop();
+                search.path.pop();
+            }
+        }
+        let mut search = Search {
+            graph: self,
+            to,
+            limit,
+            allowed,
+            steps: 0,
+            path: Vec::new(),
+            visited: vec![from],
+            found: Vec::new(),
+        };
+        walk(&mut search, from);
+        search.found
+    }
+
+    fn route(&self, path: &[Edge]) -> Route {
+        let relative: Vec<f64> = path
+            .iter()
+            .map(|edge| self.links[edge.fact].relative_uncertainty)
+            .collect();
+        Route {
+            facts: path
+                .iter()
+                .map(|edge| self.facts[edge.fact].clone())
+                .collect(),
+            measured: relative.iter().filter(|&&r| r > 0.).count(),
+            relative_uncertainty: relative.iter().map(|r| r * r).sum::<f64>().sqrt(),
+        }
+    }
+}
+
+fn prefix_factor(prefix: Option<&Prefix>) -> f64 {
+    prefix.map_or(1., |prefix| prefix.factor)
+}
+
+// How far a typed name is from a known one, if close enough to suggest.
+// Differences in case alone always count as close; otherwise a third of the
+// name may be wrong, and never all of it.
+fn closeness(typed: &str, known: &str) -> Option<usize> {
+    if typed.to_lowercase() == known.to_lowercase() {
+        return Some(0);
+    }
+    let length = typed.chars().count();
+    let distance = strsim::damerau_levenshtein(typed, known);
+    (distance <= (length / 3).max(1) && distance < length).then_some(distance)
+}
+
+// `number * multiplier / divisor`, kept as the number itself when both are 1
+// so an unprefixed fact's float factor is exactly the one it was written with.
+fn scaled(number: &Number, multiplier: &Number, divisor: &Number) -> Number {
+    if multiplier.value() == 1. && divisor.value() == 1. {
+        return number.clone();
+    }
+    Number::from_exact(number.exact() * multiplier.exact() / divisor.exact())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::uncertainty::Uncertain;
+    use super::*;
+
+    #[test]
+    fn converts_through_multiple_facts() {
+        let graph = UnitGraph::standard();
+        assert_eq!(graph.convert(2., "m", "in"), Some(78.72));
+        assert_eq!(graph.convert(120., "min", "hr"), Some(2.));
+        assert_eq!(graph.convert(1., "hr", "sec"), Some(3600.));
+    }
+
+    #[test]
+    fn added_fact_is_reachable_without_new_code() {
+        let mut graph = UnitGraph::standard();
+        graph.add_fact(Fact::new("yd", 3., "ft"));
+        assert_eq!(graph.convert(1., "yd", "in"), Some(36.));
+    }
+
+    #[test]
+    fn disconnected_units_are_not_convertible() {
+        let graph = UnitGraph::standard();
+        assert_eq!(graph.convert(13., "in", "hr"), None);
+        assert_eq!(graph.convert(1., "m", "furlong"), None);
+    }
+
+    #[test]
+    fn suggests_close_unit_names() {
+        let graph = UnitGraph::standard();
+        assert_eq!(graph.suggest("minz"), vec!["min", "mins"]);
+        assert_eq!(graph.suggest("HR"), vec!["hr"]);
+        assert_eq!(graph.suggest("kmm"), vec!["km"]);
+        assert_eq!(graph.suggest("degc")[0], "degC");
+        assert!(graph.suggest("furlong").is_empty());
+        assert!(graph.suggest("x").is_empty());
+        assert_eq!(
+            graph.lookup("fet").unwrap_err().to_string(),
+            "Unknown unit `fet`, did you mean `feet` or `ft`?"
+        );
+    }
+
+    #[test]
+    fn aliases_and_names_resolve_to_symbols() {
+        let graph = UnitGraph::standard();
+        let expr = |s: &str| s.parse::<UnitExpr>().unwrap();
+        let canonical = |s: &str| graph.canonical(&expr(s)).unwrap().to_string();
+        assert_eq!(canonical("Kilometers/hour"), "km/hr");
+        assert_eq!(canonical("feet*LBS"), "ft·lb");
+        assert_eq!(canonical("Δ°C"), "ΔdegC");
+        assert_eq!(graph.describe(&expr("in"), 78.72), "inches");
+        assert_eq!(graph.describe(&expr("'"), 1.), "foot");
+        assert_eq!(graph.describe(&expr("km"), 2.), "kilometers");
+        assert_eq!(graph.describe(&expr("m/s"), 2.), "m/s");
+        // Symbols keep their case, so mega and milli stay apart.
+        assert_eq!(graph.lookup("Mm").unwrap(), ("m", 1e6));
+        assert_eq!(graph.lookup("mm").unwrap(), ("m", 1e-3));
+        assert!(graph.lookup("FT").is_err());
+
+        let file = facts::parse_facts("unit a alias=x\nunit b alias=x\n").unwrap();
+        assert!(matches!(
+            UnitGraph::build(file),
+            Err(ConversionError::DuplicateName { .. })
+        ));
+    }
+
+    #[test]
+    fn standard_facts_are_consistent() {
+        assert!(UnitGraph::standard().inconsistencies(1e-6).is_empty());
+    }
+
+    #[test]
+    fn contradicting_fact_is_reported_with_its_cycle() {
+        let mut graph = UnitGraph::standard();
+        graph.add_fact(Fact::new("m", 40., "in"));
+        let found = graph.inconsistencies(1e-3);
+        assert_eq!(found.len(), 1);
+        assert_eq!(
+            found[0].facts,
+            vec![
+                Fact::new("m", 3.28, "ft"),
+                Fact::new("ft", 12., "in"),
+                Fact::new("m", 40., "in"),
+            ]
+        );
+        assert!((found[0].implied - 39.36).abs() < 1e-4);
+        // A looser tolerance accepts the 1.6% disagreement.
+        assert!(graph.inconsistencies(0.02).is_empty());
+    }
+
+    #[test]
+    fn checks_cycles_made_of_several_fundamental_ones() {
+        // `a = 1.00006 c` and `b = 0.99994 c` each disagree with `a = 1 b`,
+        // `b = 1 c` by 0.006%, but going round through both is 0.012% off.
+        let source = "a = 1 b\nb = 1 c\na = 1.00006 c\nb = 0.99994 c\n";
+        let graph = UnitGraph::build(facts::parse_facts(source).unwrap()).unwrap();
+        let found = graph.inconsistencies(1e-4);
+        assert_eq!(found.len(), 1);
+        assert_eq!(
+            found[0].facts,
+            vec![
+                Fact::new("a", 1., "b"),
+                Fact::new("a", 1.00006, "c"),
+                Fact::new("b", 0.99994, "c"),
+            ]
+        );
+        assert!((found[0].relative_error - 1.2e-4).abs() < 1e-8);
+        assert!(graph.inconsistencies(2e-4).is_empty());
+    }
+
+    #[test]
+    fn checks_offsets_and_compound_definitions() {
+        let temperatures = "K = 1.8 degR\ndegC = 1 K + 273.15\ndegF = 1 degR + 459.67\n";
+        let consistent = format!("{}degC = 1.8 degF + 32\n", temperatures);
+        let graph = UnitGraph::build(facts::parse_facts(&consistent).unwrap()).unwrap();
+        assert!(graph.inconsistencies(1e-9).is_empty());
+        let shifted = format!("{}degC = 1.8 degF + 33\n", temperatures);
+        let graph = UnitGraph::build(facts::parse_facts(&shifted).unwrap()).unwrap();
+        let found = graph.inconsistencies(1e-4);
+        assert_eq!(found.len(), 1);
+        assert!((found[0].implied - 1.8).abs() < 1e-9);
+        assert!((found[0].implied_offset - 32.).abs() < 1e-9);
+        assert!(found[0]
+            .to_string()
+            .ends_with("but the facts state 1.8 + 33 (3.0303% apart)"));
+
+        let source = "unit kg dim=M\nunit m dim=L\nunit s dim=T\n\
+                      N = 1 kg*m/s^2\nN = 1000 kg*m/s^2\nN = 1 kg*m/s\n";
+        let graph = UnitGraph::build(facts::parse_facts(source).unwrap()).unwrap();
+        let found = graph.inconsistencies(1e-4);
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].implied, 1.);
+        assert_eq!(found[0].stated, 1000.);
+        assert!(found[1].implied.is_nan());
+        assert!(found[1]
+            .to_string()
+            .ends_with("give N two different dimensions"));
+    }
+
+    #[test]
+    fn refuses_powers_that_nest_out_of_range() {
+        let source = "unit m dim=L\na = 1 m^100\nb = 1 a^100\n";
+        let graph = UnitGraph::build(facts::parse_facts(source).unwrap()).unwrap();
+        let b: UnitExpr = "b".parse().unwrap();
+        assert!(graph.convert_expr(BigRational::one(), &b, &b).is_ok());
+        let nested = format!("{}c = 1 b^2\n", source);
+        assert!(matches!(
+            UnitGraph::build(facts::parse_facts(&nested).unwrap()),
+            Err(ConversionError::ParseError { ref reason, .. })
+                if reason == "the power is out of range"
+        ));
+    }
+
+    #[test]
+    fn converts_compound_units_with_matching_dimensions() {
+        let graph = UnitGraph::standard();
+        let expr = |s: &str| s.parse::<UnitExpr>().unwrap();
+        let mps = graph
+            .convert_expr(60., &expr("mi/hr"), &expr("m/sec"))
+            .expect("Expected mi/hr to convert to m/sec");
+        assert!((mps - 26.829268).abs() < 1e-4);
+        let newtons = graph
+            .convert_expr(1., &expr("lb*ft/sec^2"), &expr("N"))
+            .expect("Expected lb·ft/sec² to convert to N");
+        assert!((newtons - 0.45359237 / 3.28).abs() < 1e-6);
+        assert_eq!(
+            graph.dimension(&expr("kg*m/sec^2")).unwrap(),
+            graph.dimension(&expr("N")).unwrap()
+        );
+    }
+
+    #[test]
+    fn reports_mismatched_dimensions() {
+        let graph = UnitGraph::standard();
+        let err = graph
+            .convert_expr(1., &"mi/hr".parse().unwrap(), &"m".parse().unwrap())
+            .unwrap_err();
+        assert_eq!(
+            err,
+            ConversionError::IncompatibleDimensions {
+                from: "mi/hr".to_string(),
+                to: "m".to_string(),
+                from_dimension: Dimension::base("L")
+                    .checked_div(&Dimension::base("T"))
+                    .unwrap(),
+                to_dimension: Dimension::base("L"),
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "Cannot convert mi/hr to m: incompatible dimensions L·T⁻¹ vs L"
+        );
+    }
+
+    #[test]
+    fn conflicting_dimensions_fail_to_build() {
+        let file = facts::parse_facts("unit m dim=L\nunit ft dim=T\nm = 3.28 ft\n").unwrap();
+        assert!(matches!(
+            UnitGraph::build(file),
+            Err(ConversionError::ConflictingDimensions { .. })
+        ));
+        let file = facts::parse_facts("a = 2 b/c\nb = 3 a*c\n").unwrap();
+        assert!(matches!(
+            UnitGraph::build(file),
+            Err(ConversionError::CircularDefinition { .. })
+        ));
+    }
+
+    #[test]
+    fn prefixed_units_convert_without_their_own_facts() {
+        let graph = UnitGraph::standard();
+        assert_eq!(graph.convert(2.5, "km", "m"), Some(2500.));
+        assert_eq!(graph.convert(1., "ms", "µs"), Some(1000.));
+        assert_eq!(graph.convert(1., "GiB", "MiB"), Some(1024.));
+        assert_eq!(graph.convert(1., "kB", "bit"), Some(8000.));
+        assert_eq!(graph.lookup("min").unwrap(), ("min", 1.));
+        assert!(graph.lookup("Kim").is_err());
+    }
+
+    #[test]
+    fn ambiguous_prefixed_names_need_a_declaration() {
+        let source = "unit in dim=L prefix=si\nhr = 60 min\nmin = 60 s\n";
+        let err = UnitGraph::build(facts::parse_facts(source).unwrap()).unwrap_err();
+        assert_eq!(
+            err,
+            ConversionError::PrefixCollision {
+                name: "min".to_string(),
+                prefix: "milli".to_string(),
+                unit: "in".to_string(),
+            }
+        );
+
+        let declared = format!("unit min\n{}", source);
+        let graph = UnitGraph::build(facts::parse_facts(&declared).unwrap()).unwrap();
+        assert_eq!(graph.convert(1., "hr", "min"), Some(60.));
+        assert_eq!(graph.lookup("kin").unwrap(), ("in", 1000.));
+    }
+
+    #[test]
+    fn converts_absolute_temperatures_with_offsets() {
+        let graph = UnitGraph::standard();
+        let convert = |value, from: &str, to: &str| {
+            graph
+                .convert_expr(value, &from.parse().unwrap(), &to.parse().unwrap())
+                .unwrap()
+        };
+        assert!((convert(100., "degC", "degF") - 212.).abs() < 1e-3);
+        assert!((convert(-40., "degF", "degC") + 40.).abs() < 1e-3);
+        assert!((convert(0., "degC", "K") - 273.15).abs() < 1e-3);
+        assert!((convert(300., "mK", "degC") + 272.85).abs() < 1e-3);
+        // Differences only scale.
+        assert!((convert(10., "ΔdegC", "ΔdegF") - 18.).abs() < 1e-3);
+        assert!((convert(10., "ΔdegC", "K") - 10.).abs() < 1e-3);
+        assert!((convert(1., "ΔdegC/s", "K/min") - 60.).abs() < 1e-3);
+    }
+
+    #[test]
+    fn exact_conversions_carry_rationals_along_the_path() {
+        use num_rational::BigRational;
+        let graph = UnitGraph::standard();
+        let exact = |n: i64, from: &str, to: &str| {
+            graph
+                .convert_expr(
+                    BigRational::from_integer(n.into()),
+                    &from.parse().unwrap(),
+                    &to.parse().unwrap(),
+                )
+                .unwrap()
+        };
+        assert_eq!(
+            exact(100, "degC", "degF"),
+            BigRational::from_integer(212.into())
+        );
+        assert_eq!(
+            exact(-40, "degF", "degC"),
+            BigRational::from_integer((-40).into())
+        );
+        assert_eq!(
+            exact(1, "km/hr", "mm/sec"),
+            BigRational::new(2500.into(), 9.into())
+        );
+    }
+
+    #[test]
+    fn rejects_absolute_temperatures_in_compounds() {
+        let graph = UnitGraph::standard();
+        let err = graph
+            .convert_expr(1., &"degC/s".parse().unwrap(), &"K/s".parse().unwrap())
+            .unwrap_err();
+        assert_eq!(
+            err,
+            ConversionError::AffineInCompound {
+                unit: "degC".to_string(),
+                expression: "degC/s".to_string(),
+            }
+        );
+        let err = graph
+            .convert_expr(1., &"degC".parse().unwrap(), &"ΔdegF".parse().unwrap())
+            .unwrap_err();
+        assert!(matches!(err, ConversionError::AbsoluteAndDifference { .. }));
+    }
+
+    #[test]
+    fn explains_each_step_with_its_facts() {
+        let graph = UnitGraph::standard();
+        let explain = |value: f64, from: &str, to: &str| {
+            graph
+                .explain(value, &from.parse().unwrap(), &to.parse().unwrap())
+                .expect("Expected conversion to be explained")
+        };
+
+        let explanation = explain(2., "m", "in");
+        assert_eq!(explanation.chain(), "2 m × 3.28 ft/m × 12 in/ft = 78.72 in");
+        let values: Vec<f64> = explanation.steps.iter().map(|step| step.value).collect();
+        assert_eq!(values, vec![6.56, 78.72]);
+        let source = explanation.steps[0].facts[0].source.as_ref().unwrap();
+        assert_eq!(source.file.as_deref(), Some("facts/standard.facts"));
+
+        let explanation = explain(100., "degC", "K");
+        assert_eq!(
+            explanation.chain(),
+            "100 degC × 1 K/degC + 273.15 K = 373.15 K"
+        );
+
+        let explanation = explain(1., "km", "mm");
+        assert_eq!(explanation.chain(), "1 km × 1000000 mm/km = 1000000 mm");
+        assert!(explanation.steps[0].facts.is_empty());
+
+        let explanation = explain(60., "mi/hr", "m/s");
+        assert_eq!(explanation.result, 26.829268292682926);
+        assert_eq!(explanation.steps.len(), 1);
+        let cited: Vec<String> = explanation.steps[0]
+            .facts
+            .iter()
+            .map(|fact| fact.from.clone())
+            .collect();
+        assert_eq!(cited, vec!["mi", "m", "hr", "min", "sec"]);
+    }
+
+    #[test]
+    fn converts_with_the_rates_in_effect() {
+        let mut graph = UnitGraph::standard();
+        let rates = rates::parse_rates(
+            "from,to,rate,effective,until\n\
+             EUR,USD,1.10,2023-01-01,2023-12-31\n\
+             EUR,USD,1.08,2024-01-01,\n",
+            None,
+        )
+        .unwrap();
+        graph.add_rates(rates).unwrap();
+        let eur = UnitExpr::unit("EUR");
+        let usd = UnitExpr::unit("USD");
+        assert!(matches!(
+            graph.convert_expr(2., &eur, &usd),
+            Err(ConversionError::IncompatibleDimensions { .. })
+        ));
+
+        let on = |date: &str| graph.as_of(date.parse().unwrap()).unwrap();
+        assert_eq!(on("2023-06-01").convert_expr(2., &eur, &usd), Ok(2.2));
+        assert_eq!(on("2024-06-01").convert_expr(2., &eur, &usd), Ok(2.16));
+        assert!(on("2022-06-01").convert_expr(2., &eur, &usd).is_err());
+    }
+
+    #[test]
+    fn picks_the_most_readable_unit_of_a_system() {
+        let graph = UnitGraph::standard();
+        let best = |value: f64, unit: &str, system: &str| {
+            let (value, unit) = graph
+                .to_best(value, &unit.parse().unwrap(), system)
+                .unwrap();
+            (format!("{:.4}", value), unit.to_string())
+        };
+        assert_eq!(
+            best(100000., "in", "imperial"),
+            ("1.5783".into(), "mi".into())
+        );
+        assert_eq!(
+            best(100000., "in", "metric"),
+            ("2.5407".into(), "km".into())
+        );
+        assert_eq!(best(0.5, "in", "metric"), ("12.7033".into(), "mm".into()));
+        assert_eq!(best(4992., "s", "metric"), ("1.3867".into(), "hr".into()));
+        assert_eq!(best(0.002, "s", "metric"), ("2.0000".into(), "ms".into()));
+        assert_eq!(best(1500., "g", "metric"), ("1.5000".into(), "kg".into()));
+        assert_eq!(
+            graph
+                .to_best(1., &"m/s".parse().unwrap(), "imperial")
+                .unwrap_err()
+                .to_string(),
+            "No unit in the `imperial` system measures L·T⁻¹"
+        );
+    }
+
+    #[test]
+    fn breaks_values_into_mixed_units() {
+        let graph = UnitGraph::standard();
+        let parts = |value: f64, unit: &str, parts: &[&str]| -> String {
+            let parts: Vec<UnitExpr> = parts.iter().map(|p| p.parse().unwrap()).collect();
+            graph
+                .breakdown(&Number::from(value), &unit.parse().unwrap(), &parts)
+                .unwrap()
+                .iter()
+                .map(|(value, unit)| format!("{} {}", value, unit))
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        assert_eq!(parts(71., "in", &["ft", "in"]), "5 ft 11 in");
+        assert_eq!(parts(4992., "s", &["hr", "min", "s"]), "1 hr 23 min 12 s");
+        assert_eq!(parts(-1.5, "hr", &["hr", "min"]), "-1 hr -30 min");
+        assert!(graph
+            .breakdown(
+                &Number::from(1.),
+                &"m".parse().unwrap(),
+                &["s".parse().unwrap()]
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn chooses_routes_by_policy() {
+        let source = "m = 39.37 ± 0.01 in\nm = 100 cm\nin = 2.54 cm\n";
+        let graph = UnitGraph::build(facts::parse_facts(source).unwrap()).unwrap();
+        let (m, inches): (UnitExpr, UnitExpr) = ("m".parse().unwrap(), "in".parse().unwrap());
+        let convert = |policy| graph.convert_expr_by(2., &m, &inches, policy).unwrap();
+        assert_eq!(convert(RoutePolicy::FewestHops), 78.74);
+        assert!((convert(RoutePolicy::MostExact) - 200. / 2.54).abs() < 1e-9);
+        assert!((convert(RoutePolicy::LeastUncertainty) - 200. / 2.54).abs() < 1e-9);
+
+        let explanation = graph
+            .explain_by(2., &m, &inches, RoutePolicy::MostExact)
+            .unwrap();
+        assert_eq!(explanation.rejected.len(), 1);
+        assert_eq!(
+            explanation.rejected[0].to_string(),
+            "m = 39.37 ± 0.01 in (1 fact, 1 measured, ±2.54e-4)"
+        );
+        assert!(explanation
+            .to_string()
+            .contains("chose the route with the fewest measured facts over:"));
+
+        // Without an exact route, fewer measured facts and less uncertainty
+        // part ways.
+        let source = "x = 2 ± 0.2 y\nx = 4 ± 0.004 z\nz = 0.51 ± 0.0005 y\n";
+        let graph = UnitGraph::build(facts::parse_facts(source).unwrap()).unwrap();
+        let (x, y): (UnitExpr, UnitExpr) = ("x".parse().unwrap(), "y".parse().unwrap());
+        let convert = |policy| graph.convert_expr_by(1., &x, &y, policy).unwrap();
+        assert_eq!(convert(RoutePolicy::MostExact), 2.);
+        assert!((convert(RoutePolicy::LeastUncertainty) - 2.04).abs() < 1e-9);
+    }
+
+    #[test]
+    fn counts_each_measured_fact_once() {
+        let source = "unit m dim=L\nunit s dim=T\nm = 3.28 ± 0.001 ft\nft = 12 in\n";
+        let graph = UnitGraph::build(facts::parse_facts(source).unwrap()).unwrap();
+        let convert = |from: &str, to: &str| {
+            graph
+                .convert_expr(
+                    Uncertain::new(1., 0.),
+                    &from.parse().unwrap(),
+                    &to.parse().unwrap(),
+                )
+                .unwrap()
+        };
+        // Both sides reduce to `m` through the measured fact, which cancels.
+        let speed = convert("ft/s", "in/s");
+        assert_eq!((speed.to_string(), speed.sigma), ("12".to_string(), 0.));
+        let area = convert("ft^2", "in^2");
+        assert!((area.value - 144.).abs() < 1e-9);
+        assert_eq!(area.sigma, 0.);
+        // Squared on one side only, it counts twice as much.
+        let area = convert("m^2", "ft^2");
+        assert!((area.sigma - 2. * 3.28 * 0.001).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,252 @@
+use super::expression::UnitExpr;
+use super::graph::UnitGraph;
+use super::number::{Number, Scalar};
+use super::unit_conversion::ConversionError;
+use num_rational::BigRational;
+use num_traits::Zero;
+
+// One `<value> <unit>` term of a mixed quantity, and where it starts.
+#[derive(Debug)]
+struct Term<'s> {
+    value: Number,
+    unit: &'s str,
+    position: usize,
+}
+
+/// Parses a quantity written as a sum of terms of one dimension, such as
+/// `5 ft 11 in`, `1h30m15s` or `2 hr 5 min`, and adds them up exactly in the
+/// unit of the last term: `71 in`, `5415 s` and `125 min`. A single term such
+/// as `2 m` or `2m` is a mixed quantity of one, and a leading `-` negates the
+/// whole sum, as in `-1h30m`.
+///
+/// Every term must measure the same dimension, and a sum of several terms
+/// can't hold an absolute temperature such as `degC`. Inside a sum of several
+/// terms a unit may also be written with a name from its `mixed=` attribute,
+/// which is how `m` reads as minutes in `1h30m` but as meters in `5 m 20 cm`.
+pub fn parse_mixed(graph: &UnitGraph, s: &str) -> Result<(Number, UnitExpr), ConversionError> {
+    let (negative, terms) = split_terms(s)?;
+    let mut readings = Vec::new();
+    for term in &terms {
+        let mut units = Vec::new();
+        let parsed = graph.parse_unit(term.unit);
+        if terms.len() > 1 {
+            units.extend(graph.mixed_names(term.unit).into_iter().map(UnitExpr::unit));
+        }
+        match parsed {
+            Ok(unit) => units.insert(0, unit),
+            Err(e) if units.is_empty() => return Err(e),
+            Err(_) => {}
+        }
+        // Absolute temperatures don't add up: `10 degC 5 degC` isn't 15 degC.
+        if terms.len() > 1
+            && units
+                .first()
+                .is_some_and(|unit| graph.is_absolute_temperature(unit))
+        {
+            return Err(ConversionError::ParseError {
+                input: s.to_string(),
+                position: term.position,
+                reason: format!(
+                    "`{}` is an absolute temperature, which can't be added to other terms; use `Δ{}` for a difference",
+                    term.unit, units[0]
+                ),
+            });
+        }
+        let mut dimensions = Vec::new();
+        for unit in &units {
+            dimensions.push(graph.dimension(unit)?);
+        }
+        readings.push(units.into_iter().zip(dimensions).collect::<Vec<_>>());
+    }
+
+    // The first dimension every term can be read in, preferring the usual
+    // reading of the first term.
+    let dimension = readings[0]
+        .iter()
+        .map(|(_, dimension)| dimension)
+        .find(|dimension| {
+            readings
+                .iter()
+                .all(|units| units.iter().any(|(_, d)| d == *dimension))
+        })
+        .cloned();
+    let Some(dimension) = dimension else {
+        let (first, expected) = &readings[0][0];
+        let (term, units) = terms
+            .iter()
+            .zip(&readings)
+            .find(|(_, units)| units.iter().all(|(_, d)| d != expected))
+            .expect("some term can't be read in the first term's dimension");
+        let (unit, found) = &units[0];
+        return Err(ConversionError::ParseError {
+            input: s.to_string(),
+            position: term.position,
+            reason: format!(
+                "`{}` measures {}, not {} like `{}`",
+                unit, found, expected, first
+            ),
+        });
+    };
+    let units: Vec<&UnitExpr> = readings
+        .iter()
+        .map(|units| {
+            units
+                .iter()
+                .find(|(_, d)| *d == dimension)
+                .map(|(unit, _)| unit)
+                .expect("every term has a reading in the chosen dimension")
+        })
+        .collect();
+
+    let last = units[units.len() - 1];
+    let mut total = BigRational::zero();
+    for (term, unit) in terms.iter().zip(&units) {
+        total += graph.convert_expr(BigRational::of(&term.value), unit, last)?;
+    }
+    if negative {
+        total = -total;
+    }
+    Ok((Number::from_exact(total), last.clone()))
+}
+
+// Splits `s` into its terms, each a number followed by a unit, with or without
+// a space between them. A unit runs until a space or a digit that isn't part
+// of an exponent, so `1m^12` keeps its `12`.
+fn split_terms(s: &str) -> Result<(bool, Vec<Term<'_>>), ConversionError> {
+    let invalid = |position: usize, reason: &str| ConversionError::ParseError {
+        input: s.to_string(),
+        position,
+        reason: format!("{}, expected `<value> <unit>`, e.g. `5 ft 11 in`", reason),
+    };
+    let bytes = s.as_bytes();
+    let skip_spaces = |mut i: usize| {
+        while i < s.len() && s[i..].starts_with(char::is_whitespace) {
+            i += s[i..].chars().next().map_or(1, char::len_utf8);
+        }
+        i
+    };
+    let digits = |mut i: usize| {
+        while i < s.len() && bytes[i].is_ascii_digit() {
+            i += 1;
+        }
+        i
+    };
+
+    let mut i = skip_spaces(0);
+    let negative = s[i..].starts_with('-');
+    if negative {
+        i += 1;
+    }
+    let mut terms = Vec::new();
+    loop {
+        i = skip_spaces(i);
+        if i == s.len() {
+            break;
+        }
+        let start = i;
+        i = digits(i);
+        if s[i..].starts_with('.') {
+            i = digits(i + 1);
+        }
+        // An exponent only if digits follow, so `2em` is not `2e` of `m`.
+        if s[i..].starts_with(['e', 'E']) {
+            let sign = usize::from(s[i + 1..].starts_with(['+', '-']));
+            if bytes.get(i + 1 + sign).is_some_and(u8::is_ascii_digit) {
+                i = digits(i + 1 + sign);
+            }
+        }
+        let value: Number = s[start..i]
+            .parse()
+            .map_err(|_| invalid(start, "invalid value"))?;
+
+        i = skip_spaces(i);
+        let unit_start = i;
+        let mut in_exponent = false;
+        while i < s.len() {
+            let c = s[i..].chars().next().expect("i is before the end");
+            if c.is_whitespace() || (c.is_ascii_digit() && !in_exponent) {
+                break;
+            }
+            in_exponent = c == '^'
+                || (c == '-' && s[..i].ends_with('^'))
+                || (c.is_ascii_digit() && in_exponent);
+            i += c.len_utf8();
+        }
+        if i == unit_start {
+            return Err(invalid(unit_start, "missing the unit"));
+        }
+        terms.push(Term {
+            value,
+            unit: &s[unit_start..i],
+            position: start,
+        });
+    }
+    if terms.is_empty() {
+        return Err(invalid(s.len(), "missing the value"));
+    }
+    Ok((negative, terms))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mixed(s: &str) -> String {
+        let graph = UnitGraph::standard();
+        let (value, unit) = parse_mixed(&graph, s).expect("Expected a mixed quantity");
+        format!("{} {}", value, unit)
+    }
+
+    #[test]
+    fn adds_up_terms_in_the_last_unit() {
+        assert_eq!(mixed("5 ft 11 in"), "71 in");
+        assert_eq!(mixed("1h30m15s"), "5415 s");
+        assert_eq!(mixed("2 hr 5 min"), "125 min");
+        assert_eq!(mixed("-1h30m"), "-90 min");
+        assert_eq!(mixed("5 m 20 cm"), "520 cm");
+        assert_eq!(mixed("2.5e3m"), "2500 m");
+        assert_eq!(mixed("30m"), "30 m");
+        assert_eq!(mixed("9.8 m/s^2"), "9.8 m/s²");
+        assert_eq!(mixed("1 m^12"), "1 m¹²");
+        assert_eq!(mixed("1 m^-10 2 m^-10"), "3 1/m¹⁰");
+        assert_eq!(mixed("20 degC"), "20 degC");
+        assert_eq!(mixed("10 ΔdegC 9 ΔdegF"), "27 ΔdegF");
+    }
+
+    #[test]
+    fn rejects_terms_of_other_dimensions() {
+        let graph = UnitGraph::standard();
+        let err = parse_mixed(&graph, "5 ft 3 lb").unwrap_err();
+        assert_eq!(
+            err,
+            ConversionError::ParseError {
+                input: "5 ft 3 lb".to_string(),
+                position: 5,
+                reason: "`lb` measures M, not L like `ft`".to_string(),
+            }
+        );
+        assert!(matches!(
+            parse_mixed(&graph, "5 ft 11"),
+            Err(ConversionError::ParseError { position: 7, .. })
+        ));
+        assert!(matches!(
+            parse_mixed(&graph, "5 fet"),
+            Err(ConversionError::UnknownUnit { .. })
+        ));
+    }
+
+    #[test]
+    fn refuses_to_add_up_absolute_temperatures() {
+        let graph = UnitGraph::standard();
+        for (s, position) in [("10 degC 5 degC", 0), ("10 ΔdegC 5 degF", 10)] {
+            let err = parse_mixed(&graph, s).unwrap_err();
+            assert!(
+                matches!(err, ConversionError::ParseError { position: p, ref reason, .. }
+                    if p == position && reason.contains("absolute temperature")),
+                "{:?} gave {:?}",
+                s,
+                err
+            );
+        }
+    }
+}
@@ -0,0 +1,318 @@
+use super::unit_conversion::ConversionError;
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_traits::{One, Signed, ToPrimitive, Zero};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::ops::{Add, Div, Mul, Neg, Sub};
+use std::str::FromStr;
+
+/// A number as written in a facts file or query, kept both as an `f64` and as
+/// the exact rational it spells out, so `3.28` is exactly `328/100` and `5/9`
+/// is exactly five ninths.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Number {
+    value: f64,
+    exact: BigRational,
+}
+
+impl Number {
+    pub fn value(&self) -> f64 {
+        self.value
+    }
+
+    pub fn exact(&self) -> &BigRational {
+        &self.exact
+    }
+
+    pub fn from_exact(exact: BigRational) -> Self {
+        Self {
+            value: exact.to_f64().unwrap_or(f64::NAN),
+            exact,
+        }
+    }
+}
+
+impl Neg for Number {
+    type Output = Number;
+
+    fn neg(self) -> Number {
+        Self {
+            value: -self.value,
+            exact: -self.exact,
+        }
+    }
+}
+
+/// Reads a float as the shortest decimal that prints the same, so
+/// `Number::from(3.28)` is exactly `3.28` rather than the nearest binary float.
+impl From<f64> for Number {
+    fn from(value: f64) -> Self {
+        let exact = parse_decimal(&value.to_string())
+            .ok()
+            .or_else(|| BigRational::from_float(value))
+            .unwrap_or_default();
+        Self { value, exact }
+    }
+}
+
+impl FromStr for Number {
+    type Err = ConversionError;
+
+    /// Parses a decimal such as `3.28`, `-40` or `1.5e-3`, or a fraction of two
+    /// decimals such as `5/9`. Exponents are limited to ±[`MAX_EXPONENT`].
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason: &str| ConversionError::ParseError {
+            input: s.to_string(),
+            position: 0,
+            reason: reason.to_string(),
+        };
+        let s = s.trim();
+        match s.split_once('/') {
+            None => {
+                let exact = parse_decimal(s).map_err(invalid)?;
+                let value = s.parse().map_err(|_| invalid(NOT_A_NUMBER))?;
+                Ok(Self { value, exact })
+            }
+            Some((numerator, denominator)) => {
+                let numerator = parse_decimal(numerator.trim()).map_err(invalid)?;
+                let denominator = parse_decimal(denominator.trim()).map_err(invalid)?;
+                if denominator.is_zero() {
+                    return Err(invalid(NOT_A_NUMBER));
+                }
+                Ok(Self::from_exact(numerator / denominator))
+            }
+        }
+    }
+}
+
+impl fmt::Display for Number {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match decimal_places(&self.exact) {
+            Some(places) => write!(f, "{}", to_decimal(&self.exact, places)),
+            None => write!(f, "{}/{}", self.exact.numer(), self.exact.denom()),
+        }
+    }
+}
+
+/// Written as the decimal or fraction it was read from, e.g. `"3.28"` or
+/// `"5/9"`, so the exact value survives a round trip.
+impl Serialize for Number {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+/// Reads a string such as `"3.28"` or `"5/9"` exactly, or a plain number like
+/// [`Number::from`] does.
+impl<'de> Deserialize<'de> for Number {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct NumberVisitor;
+
+        impl de::Visitor<'_> for NumberVisitor {
+            type Value = Number;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "a number or a string such as \"3.28\" or \"5/9\"")
+            }
+
+            fn visit_str<E: de::Error>(self, s: &str) -> Result<Number, E> {
+                s.parse().map_err(|e: ConversionError| E::custom(e))
+            }
+
+            fn visit_f64<E: de::Error>(self, value: f64) -> Result<Number, E> {
+                Ok(Number::from(value))
+            }
+
+            fn visit_i64<E: de::Error>(self, value: i64) -> Result<Number, E> {
+                Ok(Number::from_exact(BigRational::from_integer(value.into())))
+            }
+
+            fn visit_u64<E: de::Error>(self, value: u64) -> Result<Number, E> {
+                Ok(Number::from_exact(BigRational::from_integer(value.into())))
+            }
+        }
+
+        deserializer.deserialize_any(NumberVisitor)
+    }
+}
+
+/// The largest exponent a number may be written with, e.g. `1e400`. Beyond
+/// the range of `f64` either way, and it keeps the exact value from growing
+/// without bound: `1e99999999` would take a 100-million-digit integer.
+pub const MAX_EXPONENT: i32 = 400;
+
+const NOT_A_NUMBER: &str = "expected a number such as `3.28`, `1e-3` or `5/9`";
+
+fn parse_decimal(s: &str) -> Result<BigRational, &'static str> {
+    let (mantissa, exponent) = match s.find(['e', 'E']) {
+        Some(i) => (
+            &s[..i],
+            s[i + 1..].parse::<i64>().map_err(|_| NOT_A_NUMBER)?,
+        ),
+        None => (s, 0),
+    };
+    if exponent.abs() > MAX_EXPONENT.into() {
+        return Err("the exponent is out of range");
+    }
+    let (negative, digits) = match mantissa.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, mantissa.strip_prefix('+').unwrap_or(mantissa)),
+    };
+    let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+    let all_digits = |part: &str| part.bytes().all(|b| b.is_ascii_digit());
+    if (whole.is_empty() && fraction.is_empty()) || !all_digits(whole) || !all_digits(fraction) {
+        return Err(NOT_A_NUMBER);
+    }
+    let mut numerator: BigInt = format!("{}{}", whole, fraction)
+        .parse()
+        .map_err(|_| NOT_A_NUMBER)?;
+    if negative {
+        numerator = -numerator;
+    }
+    let ten = BigRational::from_integer(BigInt::from(10));
+    Ok(BigRational::from_integer(numerator) * ten.pow(exponent as i32 - fraction.len() as i32))
+}
+
+// How many decimal places `r` needs to be written exactly, or `None` if its
+// decimal expansion never ends (its denominator has a factor other than 2 or 5).
+fn decimal_places(r: &BigRational) -> Option<usize> {
+    let mut denominator = r.denom().clone();
+    let mut count = |factor: u32| {
+        let factor = BigInt::from(factor);
+        let mut n = 0;
+        while (&denominator % &factor).is_zero() {
+            denominator /= &factor;
+            n += 1;
+        }
+        n
+    };
+    let places = count(2).max(count(5));
+    denominator.is_one().then_some(places)
+}
+
+/// Writes `r` as a decimal rounded half away from zero to at most `places`
+/// decimal places, without trailing zeros.
+pub fn to_decimal(r: &BigRational, places: usize) -> String {
+    let scale = BigInt::from(10).pow(places as u32);
+    let scaled = (r.abs() * BigRational::from_integer(scale))
+        .round()
+        .to_integer();
+    let digits = format!("{:0>width$}", scaled, width = places + 1);
+    let (whole, fraction) = digits.split_at(digits.len() - places);
+    let fraction = fraction.trim_end_matches('0');
+    let sign = if r.is_negative() && !scaled.is_zero() {
+        "-"
+    } else {
+        ""
+    };
+    if fraction.is_empty() {
+        format!("{}{}", sign, whole)
+    } else {
+        format!("{}{}.{}", sign, whole, fraction)
+    }
+}
+
+/// Writes the result of an exact conversion: the decimal itself when it ends,
+/// otherwise the fraction followed by the decimal rounded to `places`.
+pub fn format_exact(r: &BigRational, places: usize) -> String {
+    match decimal_places(r) {
+        Some(exact_places) => to_decimal(r, exact_places),
+        None => format!("{}/{} ≈ {}", r.numer(), r.denom(), to_decimal(r, places)),
+    }
+}
+
+/// The arithmetic conversions need. Implemented for `f64` and for exact
+/// `BigRational`s, so the same path through the facts serves both.
+pub trait Scalar:
+    Clone
+    + PartialEq
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Div<Output = Self>
+{
+    fn one() -> Self;
+    fn of(number: &Number) -> Self;
+    /// A measured factor, known to within `relative_uncertainty` of itself.
+    /// Only scalars that track uncertainty keep it.
+    fn measured(number: &Number, relative_uncertainty: f64) -> Self {
+        let _ = relative_uncertainty;
+        Self::of(number)
+    }
+    fn powi(self, exponent: i32) -> Self;
+}
+
+impl Scalar for f64 {
+    fn one() -> Self {
+        1.
+    }
+
+    fn of(number: &Number) -> Self {
+        number.value
+    }
+
+    fn powi(self, exponent: i32) -> Self {
+        f64::powi(self, exponent)
+    }
+}
+
+impl Scalar for BigRational {
+    fn one() -> Self {
+        One::one()
+    }
+
+    fn of(number: &Number) -> Self {
+        number.exact.clone()
+    }
+
+    fn powi(self, exponent: i32) -> Self {
+        self.pow(exponent)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ratio(numerator: i64, denominator: i64) -> BigRational {
+        BigRational::new(numerator.into(), denominator.into())
+    }
+
+    #[test]
+    fn parses_decimals_and_fractions_exactly() {
+        let parse = |s: &str| s.parse::<Number>().expect("Expected number to parse");
+        assert_eq!(parse("3.28").exact(), &ratio(82, 25));
+        assert_eq!(parse("-1.5e-3").exact(), &ratio(-3, 2000));
+        assert_eq!(parse("5/9").exact(), &ratio(5, 9));
+        assert!((parse("5/9").value() - 5. / 9.).abs() < 1e-15);
+        assert_eq!(Number::from(0.45359237), parse("0.45359237"));
+        for bad in ["", ".", "1/0", "x", "1.2.3"] {
+            assert!(bad.parse::<Number>().is_err(), "{:?} should not parse", bad);
+        }
+    }
+
+    #[test]
+    fn refuses_huge_exponents() {
+        assert_eq!("1e400".parse::<Number>().unwrap().value(), f64::INFINITY);
+        assert_eq!("1e-400".parse::<Number>().unwrap().value(), 0.);
+        for huge in ["1e99999999", "1e-99999999", "2/1e401", "1e99999999999"] {
+            match huge.parse::<Number>() {
+                Err(ConversionError::ParseError { reason, .. }) => {
+                    assert_eq!(reason, "the exponent is out of range", "{}", huge)
+                }
+                other => panic!("Expected {} not to parse, got {:?}", huge, other),
+            }
+        }
+    }
+
+    #[test]
+    fn writes_decimals() {
+        assert_eq!(Number::from(3.28).to_string(), "3.28");
+        assert_eq!("5/9".parse::<Number>().unwrap().to_string(), "5/9");
+        assert_eq!(to_decimal(&ratio(-2, 3), 4), "-0.6667");
+        assert_eq!(to_decimal(&ratio(-1, 3000), 2), "0");
+        assert_eq!(format_exact(&ratio(1968, 25), 6), "78.72");
+        assert_eq!(format_exact(&ratio(325, 984), 6), "325/984 ≈ 0.330285");
+    }
+}
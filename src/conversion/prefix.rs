@@ -0,0 +1,113 @@
+use serde::{Deserialize, Serialize};
+
+/// Which family of prefixes a unit accepts, set with `prefix=si`,
+/// `prefix=binary` or `prefix=si,binary` on its `unit` line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PrefixSystem {
+    Si,
+    Binary,
+}
+
+impl PrefixSystem {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "si" => Some(PrefixSystem::Si),
+            "binary" => Some(PrefixSystem::Binary),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PrefixSystem::Si => "si",
+            PrefixSystem::Binary => "binary",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Prefix {
+    pub symbol: &'static str,
+    pub name: &'static str,
+    pub factor: f64,
+    pub system: PrefixSystem,
+}
+
+const fn si(symbol: &'static str, name: &'static str, factor: f64) -> Prefix {
+    Prefix {
+        symbol,
+        name,
+        factor,
+        system: PrefixSystem::Si,
+    }
+}
+
+const fn binary(symbol: &'static str, name: &'static str, power: i32) -> Prefix {
+    Prefix {
+        symbol,
+        name,
+        factor: (1u128 << (10 * power)) as f64,
+        system: PrefixSystem::Binary,
+    }
+}
+
+/// Yocto through yotta, plus kibi through yobi. Micro can be written `µ`
+/// (micro sign), `μ` (Greek mu) or `u`.
+pub const PREFIXES: [Prefix; 30] = [
+    si("y", "yocto", 1e-24),
+    si("z", "zepto", 1e-21),
+    si("a", "atto", 1e-18),
+    si("f", "femto", 1e-15),
+    si("p", "pico", 1e-12),
+    si("n", "nano", 1e-9),
+    si("µ", "micro", 1e-6),
+    si("μ", "micro", 1e-6),
+    si("u", "micro", 1e-6),
+    si("m", "milli", 1e-3),
+    si("c", "centi", 1e-2),
+    si("d", "deci", 1e-1),
+    si("da", "deca", 1e1),
+    si("h", "hecto", 1e2),
+    si("k", "kilo", 1e3),
+    si("M", "mega", 1e6),
+    si("G", "giga", 1e9),
+    si("T", "tera", 1e12),
+    si("P", "peta", 1e15),
+    si("E", "exa", 1e18),
+    si("Z", "zetta", 1e21),
+    si("Y", "yotta", 1e24),
+    binary("Ki", "kibi", 1),
+    binary("Mi", "mebi", 2),
+    binary("Gi", "gibi", 3),
+    binary("Ti", "tebi", 4),
+    binary("Pi", "pebi", 5),
+    binary("Ei", "exbi", 6),
+    binary("Zi", "zebi", 7),
+    binary("Yi", "yobi", 8),
+];
+
+/// Every way `name` splits into a prefix and a non-empty remainder, e.g.
+/// `km` into kilo + `m`. Whether the remainder is a prefixable unit is up to
+/// the caller.
+pub fn split_prefix(name: &str) -> impl Iterator<Item = (&'static Prefix, &str)> {
+    PREFIXES.iter().filter_map(move |prefix| {
+        name.strip_prefix(prefix.symbol)
+            .filter(|rest| !rest.is_empty())
+            .map(|rest| (prefix, rest))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_every_candidate_prefix() {
+        let splits: Vec<(&str, &str)> = split_prefix("dam").map(|(p, r)| (p.name, r)).collect();
+        assert_eq!(splits, vec![("deci", "am"), ("deca", "m")]);
+        let (gibi, rest) = split_prefix("GiB").find(|(p, _)| p.name == "gibi").unwrap();
+        assert_eq!((gibi.factor, rest), (1073741824., "B"));
+        assert_eq!(split_prefix("m").count(), 0);
+    }
+}
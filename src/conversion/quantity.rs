@@ -0,0 +1,413 @@
+use super::expression::UnitExpr;
+use super::graph::UnitGraph;
+use super::mixed;
+use super::number::Number;
+use super::unit_conversion::{standard_graph, ConversionError};
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::{Add, Div, Mul, Neg, Sub};
+use std::str::FromStr;
+
+/// A value with its unit, e.g. `3.5 ft` or `9.8 m/s²`, for code that works
+/// with measurements rather than single conversions.
+///
+/// Adding or subtracting converts the right-hand side into the left-hand
+/// side's unit first, and fails with `IncompatibleDimensions` if it can't be.
+/// Multiplying and dividing combine the units, so `2 m * 3 m` is `6 m²`.
+///
+/// Absolute temperatures such as `degC` only add or subtract a difference
+/// (`10 degC + 5 ΔdegC` is `15 degC`), and subtracting one from another
+/// gives a difference (`30 degC - 10 degC` is `20 ΔdegC`). Adding two, or
+/// multiplying or dividing by one, fails with `AffineInCompound`.
+/// Quantities compare equal, or order, when they measure the same amount,
+/// whatever their units.
+#[derive(Debug, Clone)]
+pub struct Quantity<'a> {
+    value: f64,
+    unit: UnitExpr,
+    graph: &'a UnitGraph,
+}
+
+impl Quantity<'static> {
+    /// A quantity in the standard facts' units. The unit may use aliases or
+    /// full names, which are rewritten to symbols.
+    pub fn new(value: f64, unit: &str) -> Result<Self, ConversionError> {
+        Self::with_graph(standard_graph(), value, unit)
+    }
+}
+
+impl<'a> Quantity<'a> {
+    /// A quantity in the units of `graph` instead of the standard facts.
+    pub fn with_graph(
+        graph: &'a UnitGraph,
+        value: f64,
+        unit: &str,
+    ) -> Result<Self, ConversionError> {
+        Ok(Self {
+            value,
+            unit: graph.parse_unit(unit)?,
+            graph,
+        })
+    }
+
+    /// Parses a quantity such as `3.5 ft`, `2m`, `60 mi/hr` or a mixed one
+    /// such as `5 ft 11 in` in the units of `graph`. A mixed quantity is added
+    /// up in the unit of its last term; see [`mixed::parse_mixed`].
+    pub fn parse_with(graph: &'a UnitGraph, s: &str) -> Result<Self, ConversionError> {
+        let (value, unit) = mixed::parse_mixed(graph, s)?;
+        Ok(Self {
+            value: value.value(),
+            unit,
+            graph,
+        })
+    }
+
+    pub fn value(&self) -> f64 {
+        self.value
+    }
+
+    /// The unit, written with symbols.
+    pub fn unit(&self) -> &UnitExpr {
+        &self.unit
+    }
+
+    /// The same amount in `unit`.
+    pub fn to(&self, unit: &str) -> Result<Self, ConversionError> {
+        self.to_expr(&self.graph.parse_unit(unit)?)
+    }
+
+    /// The same amount in the most readable unit of `system`, e.g. `1.578 mi`
+    /// for `100000 in` in `imperial`. See [`UnitGraph::to_best`].
+    pub fn to_best(&self, system: &str) -> Result<Self, ConversionError> {
+        let (value, unit) = self.graph.to_best(self.value, &self.unit, system)?;
+        Ok(Self {
+            value,
+            unit,
+            graph: self.graph,
+        })
+    }
+
+    /// The same amount split across `units`, e.g. `71 in` across `ft, in` as
+    /// `5 ft` and `11 in`. See [`UnitGraph::breakdown`].
+    pub fn breakdown(&self, units: &[&str]) -> Result<Vec<Self>, ConversionError> {
+        let parts = units
+            .iter()
+            .map(|unit| self.graph.parse_unit(unit))
+            .collect::<Result<Vec<_>, _>>()?;
+        let split = self
+            .graph
+            .breakdown(&Number::from(self.value), &self.unit, &parts)?;
+        Ok(split
+            .into_iter()
+            .map(|(value, unit)| Self {
+                value,
+                unit,
+                graph: self.graph,
+            })
+            .collect())
+    }
+
+    fn to_expr(&self, unit: &UnitExpr) -> Result<Self, ConversionError> {
+        Ok(Self {
+            value: self.graph.convert_expr(self.value, &self.unit, unit)?,
+            unit: unit.clone(),
+            graph: self.graph,
+        })
+    }
+
+    // Adds `other` times `sign` (1 or -1), minding which sides are absolute
+    // temperatures and which are differences of them.
+    fn add_signed(self, other: Quantity<'a>, sign: f64) -> Result<Self, ConversionError> {
+        let absolute = self.graph.is_absolute_temperature(&self.unit);
+        let other_absolute = self.graph.is_absolute_temperature(&other.unit);
+        let value = |other: &Quantity<'_>| self.value + sign * other.value;
+        match (absolute, other_absolute) {
+            (true, true) if sign < 0. => {
+                let other = other.to_expr(&self.unit)?;
+                Ok(Self {
+                    value: value(&other),
+                    unit: difference(&self.unit),
+                    ..self
+                })
+            }
+            (true, false) => {
+                let other = other.to_expr(&difference(&self.unit))?;
+                Ok(Self {
+                    value: value(&other),
+                    ..self
+                })
+            }
+            // A difference plus an absolute temperature is that temperature
+            // moved, given in the scale the difference was measured in.
+            (false, true) if sign > 0. => {
+                let scale = self
+                    .unit
+                    .as_simple()
+                    .and_then(|symbol| symbol.strip_prefix('Δ'))
+                    .map(UnitExpr::unit);
+                let sum = other.add_signed(self, sign)?;
+                match scale {
+                    Some(scale) => sum.to_expr(&scale),
+                    None => Ok(sum),
+                }
+            }
+            (false, false) => {
+                let other = other.to_expr(&self.unit)?;
+                Ok(Self {
+                    value: value(&other),
+                    ..self
+                })
+            }
+            _ => Err(self.affine_in(&other, if sign > 0. { "+" } else { "-" })),
+        }
+    }
+
+    // The error for an absolute temperature on either side of `operator`.
+    fn affine_in(&self, other: &Quantity<'_>, operator: &str) -> ConversionError {
+        let absolute = if self.graph.is_absolute_temperature(&self.unit) {
+            &self.unit
+        } else {
+            &other.unit
+        };
+        ConversionError::AffineInCompound {
+            unit: absolute.to_string(),
+            expression: format!("{} {} {}", self, operator, other),
+        }
+    }
+
+    // Combines the units of `self` and `other`, the latter raised to `power`.
+    fn combine(
+        self,
+        other: &Quantity<'_>,
+        power: i32,
+        value: f64,
+    ) -> Result<Self, ConversionError> {
+        let graph = self.graph;
+        if graph.is_absolute_temperature(&self.unit) || graph.is_absolute_temperature(&other.unit) {
+            return Err(self.affine_in(other, if power > 0 { "*" } else { "/" }));
+        }
+        let terms = self.unit.terms().iter().cloned().chain(
+            other
+                .unit
+                .terms()
+                .iter()
+                .map(|(symbol, exponent)| (symbol.clone(), exponent * power)),
+        );
+        Ok(Self {
+            value,
+            unit: UnitExpr::from_terms(terms)?,
+            graph: self.graph,
+        })
+    }
+}
+
+// The difference form of an absolute temperature, `ΔdegC` for `degC`.
+fn difference(absolute: &UnitExpr) -> UnitExpr {
+    UnitExpr::unit(&format!("Δ{}", absolute))
+}
+
+impl FromStr for Quantity<'static> {
+    type Err = ConversionError;
+
+    /// Parses a quantity in the standard facts' units, e.g. `3.5 ft`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse_with(standard_graph(), s)
+    }
+}
+
+impl fmt::Display for Quantity<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // A ratio of like units, such as `m/m`, is a plain number.
+        match self.unit.terms() {
+            [] => write!(f, "{}", self.value),
+            _ => write!(f, "{} {}", self.value, self.unit),
+        }
+    }
+}
+
+impl<'a> Add for Quantity<'a> {
+    type Output = Result<Quantity<'a>, ConversionError>;
+
+    fn add(self, other: Quantity<'a>) -> Self::Output {
+        self.add_signed(other, 1.)
+    }
+}
+
+impl<'a> Sub for Quantity<'a> {
+    type Output = Result<Quantity<'a>, ConversionError>;
+
+    fn sub(self, other: Quantity<'a>) -> Self::Output {
+        self.add_signed(other, -1.)
+    }
+}
+
+impl Neg for Quantity<'_> {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self {
+            value: -self.value,
+            ..self
+        }
+    }
+}
+
+impl<'a> Mul for Quantity<'a> {
+    type Output = Result<Quantity<'a>, ConversionError>;
+
+    fn mul(self, other: Quantity<'a>) -> Self::Output {
+        let value = self.value * other.value;
+        self.combine(&other, 1, value)
+    }
+}
+
+impl<'a> Div for Quantity<'a> {
+    type Output = Result<Quantity<'a>, ConversionError>;
+
+    fn div(self, other: Quantity<'a>) -> Self::Output {
+        let value = self.value / other.value;
+        self.combine(&other, -1, value)
+    }
+}
+
+impl Mul<f64> for Quantity<'_> {
+    type Output = Self;
+
+    fn mul(self, factor: f64) -> Self {
+        Self {
+            value: self.value * factor,
+            ..self
+        }
+    }
+}
+
+impl Div<f64> for Quantity<'_> {
+    type Output = Self;
+
+    fn div(self, divisor: f64) -> Self {
+        Self {
+            value: self.value / divisor,
+            ..self
+        }
+    }
+}
+
+impl PartialEq for Quantity<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.partial_cmp(other) == Some(Ordering::Equal)
+    }
+}
+
+/// Quantities of different dimensions are unordered.
+impl PartialOrd for Quantity<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        let other = other.to_expr(&self.unit).ok()?;
+        self.value.partial_cmp(&other.value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quantity(s: &str) -> Quantity<'static> {
+        s.parse().expect("Expected quantity to parse")
+    }
+
+    #[test]
+    fn parses_and_converts() {
+        let length = quantity("3.5 ft");
+        assert_eq!(length.value(), 3.5);
+        assert_eq!(length.unit().to_string(), "ft");
+        assert_eq!(quantity("2m").to("in").unwrap().value(), 78.72);
+        assert_eq!(quantity("2 meters").unit().to_string(), "m");
+        assert!(matches!(
+            "2 fet".parse::<Quantity>(),
+            Err(ConversionError::UnknownUnit { .. })
+        ));
+        assert!(matches!(
+            "ft".parse::<Quantity>(),
+            Err(ConversionError::ParseError { position: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn adds_in_the_left_hand_unit() {
+        let sum = (quantity("1 ft") + quantity("6 in")).unwrap();
+        assert_eq!(sum.to_string(), "1.5 ft");
+        let difference = (quantity("1 hr") - quantity("30 min")).unwrap();
+        assert_eq!(difference.to_string(), "0.5 hr");
+        assert!(matches!(
+            quantity("1 m") + quantity("1 s"),
+            Err(ConversionError::IncompatibleDimensions { .. })
+        ));
+    }
+
+    #[test]
+    fn multiplies_into_compound_units() {
+        let area = (quantity("2 m") * quantity("3 m")).unwrap();
+        assert_eq!(area.to_string(), "6 m²");
+        let speed = (quantity("120 mi") / quantity("2 hr")).unwrap();
+        assert_eq!(speed.to_string(), "60 mi/hr");
+        assert!((speed.to("m/s").unwrap().value() - 26.829268292682926).abs() < 1e-12);
+        assert_eq!(
+            (quantity("4 m") / quantity("2 m")).unwrap().to_string(),
+            "2"
+        );
+        assert_eq!((quantity("2 m") * 3.).to_string(), "6 m");
+    }
+
+    #[test]
+    fn keeps_absolute_temperatures_and_differences_apart() {
+        let warmer = (quantity("10 degC") + quantity("9 ΔdegF")).unwrap();
+        assert_eq!(warmer.to_string(), "15 degC");
+        let cooler = (quantity("10 degC") - quantity("5 ΔdegC")).unwrap();
+        assert_eq!(cooler.to_string(), "5 degC");
+        let rise = (quantity("30 degC") - quantity("50 degF")).unwrap();
+        assert_eq!(rise.unit().to_string(), "ΔdegC");
+        assert!((rise.value() - 20.).abs() < 1e-9);
+        let moved = (quantity("9 ΔdegF") + quantity("10 degC")).unwrap();
+        assert_eq!(moved.unit().to_string(), "degF");
+        assert!((moved.value() - 59.).abs() < 1e-9);
+        assert_eq!(
+            (quantity("5 ΔdegC") + quantity("5 ΔdegC"))
+                .unwrap()
+                .to_string(),
+            "10 ΔdegC"
+        );
+
+        let affine = |result: Result<Quantity, ConversionError>| matches!(result, Err(ConversionError::AffineInCompound { unit, .. }) if unit == "degC");
+        assert!(affine(quantity("10 degC") + quantity("5 degC")));
+        assert!(affine(quantity("5 ΔdegF") - quantity("10 degC")));
+        assert!(affine(quantity("1 degC") * quantity("2 m")));
+        assert!(affine(quantity("2 m") / quantity("1 degC")));
+        assert_eq!(
+            (quantity("1 degC") * quantity("2 m")).unwrap_err().to_string(),
+            "Cannot use the absolute temperature `degC` in `1 degC * 2 m`, use `ΔdegC` for a temperature difference"
+        );
+        assert!((quantity("1 ΔdegC") * quantity("2 m")).is_ok());
+    }
+
+    #[test]
+    fn compares_across_units() {
+        assert!(quantity("1 hr") == quantity("60 min"));
+        assert!(quantity("1 m") > quantity("3 ft"));
+        assert!(quantity("1 in") < quantity("1 ft"));
+        assert_eq!(quantity("1 m").partial_cmp(&quantity("1 s")), None);
+        assert!(quantity("1 m") != quantity("1 s"));
+    }
+
+    #[test]
+    fn picks_readable_and_mixed_units() {
+        let best = quantity("100000 in").to_best("metric").unwrap();
+        assert_eq!(best.unit().to_string(), "km");
+        assert!((best.value() - 2.5407).abs() < 1e-4);
+        let height: Vec<String> = quantity("71 in")
+            .breakdown(&["ft", "in"])
+            .unwrap()
+            .iter()
+            .map(Quantity::to_string)
+            .collect();
+        assert_eq!(height, ["5 ft", "11 in"]);
+    }
+}
@@ -0,0 +1,325 @@
+use super::explain::Explanation;
+use super::format::{Format, Precision};
+use super::graph::UnitGraph;
+use super::number::Number;
+use super::route::RoutePolicy;
+use super::uncertainty::{self, Uncertain};
+use super::unit_conversion::{ConversionError, ConversionQuery};
+use num_rational::BigRational;
+use std::fmt;
+use std::str::FromStr;
+
+/// What a query prints when its units can't be converted.
+pub const NOT_CONVERTIBLE: &str = "not convertible!";
+
+/// How many decimal places an exact answer that never ends is rounded to.
+pub const EXACT_PLACES: usize = 15;
+
+/// A query in the README's syntax, e.g. `2 m = ? in` or `13in = ? m`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Query {
+    pub value: Number,
+    /// The standard uncertainty of `value`, 0 if none was given.
+    pub uncertainty: f64,
+    pub from: String,
+    pub to: String,
+}
+
+impl Query {
+    pub fn answer(&self, graph: &UnitGraph) -> Result<f64, ConversionError> {
+        ConversionQuery::convert_with(graph, self.value.clone(), &self.from, &self.to)
+    }
+
+    pub fn answer_exact(&self, graph: &UnitGraph) -> Result<BigRational, ConversionError> {
+        ConversionQuery::convert_exact_with(graph, self.value.clone(), &self.from, &self.to)
+    }
+
+    /// Converts like [`Query::answer`], propagating the uncertainty of the
+    /// value and of the measured facts used.
+    pub fn answer_uncertain(&self, graph: &UnitGraph) -> Result<Uncertain, ConversionError> {
+        ConversionQuery::with_graph(graph, self.value.clone(), &self.from, &self.to)?
+            .with_uncertainty(self.uncertainty)
+            .convert_uncertain()
+    }
+
+    pub fn explain(&self, graph: &UnitGraph) -> Result<Explanation, ConversionError> {
+        ConversionQuery::with_graph(graph, self.value.clone(), &self.from, &self.to)?.explain()
+    }
+}
+
+impl FromStr for Query {
+    type Err = ConversionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |position: usize, reason: &str| ConversionError::ParseError {
+            input: s.to_string(),
+            position,
+            reason: format!("{}, expected `<value> <unit> = ? <unit>`", reason),
+        };
+        // Where a slice of `s` starts within it.
+        let at = |part: &str| part.as_ptr() as usize - s.as_ptr() as usize;
+        let (lhs, rhs) = s
+            .split_once('=')
+            .ok_or_else(|| invalid(s.len(), "missing `=`"))?;
+
+        let split_number = |part| {
+            split_number(part).ok_or_else(|| invalid(at(part) + part.len(), "missing the unit"))
+        };
+        // A measured value is followed by its uncertainty, as in `2 ± 0.01 m`.
+        let lhs = lhs.trim();
+        let (value, uncertainty, from) = match uncertainty::split_uncertainty(lhs) {
+            Some((value, rest)) => {
+                let (uncertainty, from) = split_number(rest.trim_start())?;
+                let uncertainty = uncertainty::parse_uncertainty(uncertainty)
+                    .ok_or_else(|| invalid(at(uncertainty), "invalid uncertainty"))?;
+                (value, uncertainty, from)
+            }
+            None => {
+                let (value, from) = split_number(lhs)?;
+                (value, 0., from)
+            }
+        };
+        let value: Number = value
+            .trim()
+            .parse()
+            .map_err(|_| invalid(at(value), "invalid value"))?;
+        let from = from.trim();
+
+        let rhs = rhs.trim();
+        let to = rhs
+            .strip_prefix('?')
+            .ok_or_else(|| invalid(at(rhs), "missing `?`"))?
+            .trim();
+        for unit in [from, to] {
+            if unit.is_empty() {
+                return Err(invalid(at(unit), "missing a unit"));
+            }
+            if let Some(space) = unit.find(' ') {
+                return Err(invalid(at(unit) + space, "unexpected space in a unit"));
+            }
+        }
+
+        Ok(Self {
+            value,
+            uncertainty,
+            from: from.to_string(),
+            to: to.to_string(),
+        })
+    }
+}
+
+// Splits a number from the unit after it, which may be written together, as
+// in `2m`.
+fn split_number(part: &str) -> Option<(&str, &str)> {
+    match part.split_once(char::is_whitespace) {
+        Some(parts) => Some(parts),
+        None => part
+            .find(|c: char| !(c.is_ascii_digit() || matches!(c, '.' | '-' | '+')))
+            .map(|unit_start| part.split_at(unit_start)),
+    }
+}
+
+impl fmt::Display for Query {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.value)?;
+        if self.uncertainty > 0. {
+            write!(f, " ± {}", self.uncertainty)?;
+        }
+        write!(f, " {} = ? {}", self.from, self.to)
+    }
+}
+
+/// Writes an answer with `format`. A value with an uncertainty is rounded to
+/// it, as [`Uncertain`] writes it, and an exact answer is followed by the
+/// uncertainty of the same conversion in floating point. A chosen precision,
+/// notation or separator applies to both the value and the uncertainty.
+pub fn format_answer(
+    format: &Format,
+    uncertain: &Uncertain,
+    exact: Option<&BigRational>,
+) -> String {
+    let Some(sigma) = uncertain.format_sigma() else {
+        return match exact {
+            Some(exact) => format.exact(exact),
+            None => format.number(uncertain.value),
+        };
+    };
+    if *format == Format::default() {
+        return match exact {
+            Some(exact) => format!("{} ± {}", format.exact(exact), sigma),
+            None => uncertain.to_string(),
+        };
+    }
+    // Without a precision of its own, the answer keeps the digits the
+    // uncertainty leaves meaningful.
+    let shown = match format.precision {
+        Precision::Shortest => uncertain.rounded(),
+        _ => *uncertain,
+    };
+    let value = match exact {
+        Some(exact) => format.exact(exact),
+        None => format.number(shown.value),
+    };
+    format!("{} ± {}", value, format.number(shown.sigma))
+}
+
+/// How query answers are computed and written.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct AnswerOptions {
+    /// Compute with exact ratios and write a decimal only at the end.
+    pub exact: bool,
+    /// Follow each answer with the steps and facts that produced it.
+    pub explain: bool,
+    pub format: Format,
+    /// How to choose between several chains of facts connecting the units.
+    pub route: RoutePolicy,
+}
+
+/// Answers a single query line: the converted value, `not convertible!`, or
+/// an error message if the line isn't a query or names an unknown unit. The
+/// value is followed by its uncertainty, as in `78.72 ± 0.03`, when the query
+/// or the facts it uses have one.
+pub fn answer_line(graph: &UnitGraph, line: &str, options: AnswerOptions) -> String {
+    let query = match line.parse::<Query>() {
+        Ok(query) => query,
+        Err(e) => return format!("error: {}", e),
+    };
+    let answer = ConversionQuery::with_graph(graph, query.value, &query.from, &query.to).and_then(
+        |conversion| {
+            let conversion = conversion
+                .with_uncertainty(query.uncertainty)
+                .with_route(options.route);
+            let uncertain = conversion.convert_uncertain()?;
+            let exact = match options.exact {
+                true => Some(conversion.convert_units_exact()?),
+                false => None,
+            };
+            let answer = format_answer(&options.format, &uncertain, exact.as_ref());
+            match options.explain {
+                true => Ok(format!("{}\n{}", answer, conversion.explain()?)),
+                false => Ok(answer),
+            }
+        },
+    );
+    match answer {
+        Ok(answer) => answer,
+        Err(e @ ConversionError::UnknownUnit { .. }) => format!("error: {}", e),
+        Err(_) => NOT_CONVERTIBLE.to_string(),
+    }
+}
+
+/// Answers every query in `source`, one answer per query in the same order.
+/// Blank lines and `#` comments are skipped, like in a facts file.
+pub fn answer_batch(graph: &UnitGraph, source: &str, options: AnswerOptions) -> Vec<String> {
+    source
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or_default().trim())
+        .filter(|line| !line.is_empty())
+        .map(|line| answer_line(graph, line, options))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conversion::facts;
+    use crate::conversion::format::Notation;
+
+    #[test]
+    fn parses_readme_queries() {
+        let query: Query = "2m = ? in".parse().expect("Expected query to parse");
+        assert_eq!(
+            query,
+            Query {
+                value: Number::from(2.),
+                uncertainty: 0.,
+                from: "m".to_string(),
+                to: "in".to_string(),
+            }
+        );
+        assert!(matches!(
+            "13 in = in".parse::<Query>(),
+            Err(ConversionError::ParseError { position: 8, .. })
+        ));
+        assert!("in = ? m".parse::<Query>().is_err());
+
+        let query: Query = "2 ± 0.01 m = ? in".parse().unwrap();
+        assert_eq!(query.uncertainty, 0.01);
+        assert_eq!(query.from, "m");
+        assert_eq!("2+/-0.01m = ? in".parse::<Query>().unwrap(), query);
+        assert!(matches!(
+            "2 ± x m = ? in".parse::<Query>(),
+            Err(ConversionError::ParseError { position: 5, .. })
+        ));
+    }
+
+    #[test]
+    fn propagates_uncertainty_into_answers() {
+        let graph =
+            UnitGraph::build(facts::parse_facts("m = 3.28 ± 0.001 ft\nft = 12 in\n").unwrap())
+                .unwrap();
+        let options = AnswerOptions::default();
+        assert_eq!(answer_line(&graph, "2 m = ? in", options), "78.72 ± 0.03");
+        assert_eq!(answer_line(&graph, "24 in = ? ft", options), "2");
+        assert_eq!(answer_line(&graph, "2 ± 0.5 ft = ? in", options), "24 ± 6");
+        let exact = AnswerOptions {
+            exact: true,
+            ..options
+        };
+        assert_eq!(answer_line(&graph, "2 m = ? in", exact), "78.72 ± 0.03");
+
+        let formatted = |format: Format| AnswerOptions { format, ..options };
+        let sig_figs = formatted(Format {
+            precision: Precision::Significant(2),
+            ..Format::default()
+        });
+        assert_eq!(answer_line(&graph, "2 m = ? in", sig_figs), "79 ± 0.024");
+        let scientific = formatted(Format {
+            notation: Notation::Scientific,
+            ..Format::default()
+        });
+        assert_eq!(
+            answer_line(&graph, "2 m = ? in", scientific),
+            "7.872e1 ± 3e-2"
+        );
+        let grouped = formatted(Format {
+            precision: Precision::Decimals(1),
+            separator: Some(','),
+            ..Format::default()
+        });
+        assert_eq!(
+            answer_line(&graph, "2000 m = ? in", grouped),
+            "78,720.0 ± 24.0"
+        );
+    }
+
+    #[test]
+    fn answers_batch_in_order() {
+        let graph = UnitGraph::standard();
+        let answers = answer_batch(
+            &graph,
+            "2 m = ? in\n\n# skipped\n13 in = ? hr\n2 hr = ? min\n",
+            AnswerOptions::default(),
+        );
+        assert_eq!(answers, vec!["78.72", NOT_CONVERTIBLE, "120"]);
+
+        assert_eq!(
+            answer_line(&graph, "1 fet = ? in", AnswerOptions::default()),
+            "error: Unknown unit `fet`, did you mean `feet` or `ft`?"
+        );
+
+        let exact = AnswerOptions {
+            exact: true,
+            ..AnswerOptions::default()
+        };
+        let answers = answer_batch(&graph, "0.1 m = ? in\n13 in = ? m\n", exact);
+        assert_eq!(answers, vec!["3.936", "325/984 ≈ 0.330284552845528"]);
+
+        let explain = AnswerOptions {
+            explain: true,
+            ..AnswerOptions::default()
+        };
+        let answer = answer_line(&graph, "2 m = ? in", explain);
+        assert!(answer.starts_with("78.72\n2 m × 3.28 ft/m × 12 in/ft = 78.72 in\n"));
+    }
+}
@@ -0,0 +1,192 @@
+use super::facts::{Fact, Source};
+use super::number::Number;
+use super::unit_conversion::ConversionError;
+use chrono::NaiveDate;
+use std::fs;
+use std::path::Path;
+
+/// A conversion rate that holds for a range of dates, such as an exchange
+/// rate: `1 from = factor to` from `effective` through `until` (inclusive),
+/// or indefinitely if `until` is `None`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rate {
+    pub from: String,
+    pub factor: Number,
+    pub to: String,
+    pub effective: NaiveDate,
+    pub until: Option<NaiveDate>,
+    /// Where the rate was read from, if it came from a rates file.
+    pub source: Option<Source>,
+}
+
+impl Rate {
+    pub fn in_effect(&self, date: NaiveDate) -> bool {
+        self.effective <= date && self.until.is_none_or(|until| date <= until)
+    }
+
+    /// The rate as a fact, for conversions on a date it is in effect.
+    pub fn fact(&self) -> Fact {
+        Fact {
+            from: self.from.clone(),
+            factor: self.factor.clone(),
+            uncertainty: 0.,
+            to: self.to.clone(),
+            offset: Number::from(0.),
+            source: self.source.clone(),
+        }
+    }
+}
+
+/// The rates in effect on `date`, one per pair of units. Where several
+/// ranges for the same pair cover the date, the one that took effect last
+/// wins.
+pub fn in_effect(rates: &[Rate], date: NaiveDate) -> Vec<&Rate> {
+    let mut current: Vec<&Rate> = Vec::new();
+    for rate in rates.iter().filter(|rate| rate.in_effect(date)) {
+        let same_pair = current
+            .iter_mut()
+            .find(|other| other.from == rate.from && other.to == rate.to);
+        match same_pair {
+            Some(other) if other.effective <= rate.effective => *other = rate,
+            Some(_) => {}
+            None => current.push(rate),
+        }
+    }
+    current
+}
+
+/// Parses a CSV of rates with the header `from,to,rate,effective,until`,
+/// e.g. `EUR,USD,1.08,2024-01-01,2024-06-30`. Dates are `YYYY-MM-DD` and an
+/// empty `until` leaves the rate in effect. Lines starting with `#` are
+/// skipped.
+pub fn parse_rates(source: &str, file: Option<&str>) -> Result<Vec<Rate>, ConversionError> {
+    let origin = file.map(String::from);
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .flexible(true)
+        .has_headers(false)
+        .from_reader(source.as_bytes());
+    let mut rates = Vec::new();
+    let mut header = true;
+    for record in reader.records() {
+        let record = record.map_err(|e| ConversionError::InvalidFact {
+            path: origin.clone(),
+            line: e.position().map_or(0, |p| p.line() as usize),
+            text: String::new(),
+            reason: e.to_string(),
+        })?;
+        // Comments and the header are skipped here rather than by the
+        // reader, which would leave them out of the line numbers.
+        if record.get(0).is_some_and(|first| first.starts_with('#')) {
+            continue;
+        }
+        if std::mem::take(&mut header) {
+            continue;
+        }
+        let line = record.position().map_or(0, |p| p.line() as usize);
+        let invalid = |reason: String| ConversionError::InvalidFact {
+            path: origin.clone(),
+            line,
+            text: record.iter().collect::<Vec<_>>().join(","),
+            reason,
+        };
+        let [from, to, factor, effective, until] = [0, 1, 2, 3, 4].map(|i| record.get(i));
+        let (Some(from), Some(to), Some(factor), Some(effective)) = (from, to, factor, effective)
+        else {
+            return Err(invalid(
+                "expected `from,to,rate,effective,until`".to_string(),
+            ));
+        };
+        let factor: Number = factor
+            .parse()
+            .map_err(|_| invalid(format!("invalid rate `{}`", factor)))?;
+        if !factor.value().is_finite() || factor.value() <= 0. {
+            return Err(invalid(format!("rate must be positive, got {}", factor)));
+        }
+        let date = |date: &str| {
+            NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                .map_err(|_| invalid(format!("invalid date `{}`, expected YYYY-MM-DD", date)))
+        };
+        let effective = date(effective)?;
+        let until = match until.unwrap_or_default() {
+            "" => None,
+            until => Some(date(until)?),
+        };
+        if until.is_some_and(|until| until < effective) {
+            return Err(invalid("the rate ends before it takes effect".to_string()));
+        }
+        rates.push(Rate {
+            from: from.to_string(),
+            factor,
+            to: to.to_string(),
+            effective,
+            until,
+            source: Some(Source {
+                file: origin.clone(),
+                line,
+            }),
+        });
+    }
+    Ok(rates)
+}
+
+pub fn load_rates<P: AsRef<Path>>(path: P) -> Result<Vec<Rate>, ConversionError> {
+    let path = path.as_ref();
+    let source = fs::read_to_string(path).map_err(|e| ConversionError::ReadFacts {
+        path: path.display().to_string(),
+        reason: e.to_string(),
+    })?;
+    parse_rates(&source, Some(&path.display().to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATES: &str = "\
+# exchange rates
+from,to,rate,effective,until
+EUR,USD,1.10,2023-01-01,2023-12-31
+EUR,USD,1.08,2024-01-01,
+GBP,USD,1.27,2024-01-01,2024-06-30
+";
+
+    fn date(s: &str) -> NaiveDate {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_rates_with_their_lines() {
+        let rates = parse_rates(RATES, Some("rates.csv")).expect("Expected rates to parse");
+        assert_eq!(rates.len(), 3);
+        assert_eq!(rates[1].factor, Number::from(1.08));
+        assert_eq!(rates[1].until, None);
+        assert_eq!(rates[2].until, Some(date("2024-06-30")));
+        assert_eq!(rates[0].fact().source.unwrap().to_string(), "rates.csv:3");
+
+        let err = parse_rates(
+            "from,to,rate,effective,until\nEUR,USD,1.1,2024-13-01,\n",
+            None,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "line 2: invalid date `2024-13-01`, expected YYYY-MM-DD in `EUR,USD,1.1,2024-13-01,`"
+        );
+    }
+
+    #[test]
+    fn picks_the_rate_in_effect() {
+        let rates = parse_rates(RATES, None).unwrap();
+        let factors = |day: &str| -> Vec<String> {
+            in_effect(&rates, date(day))
+                .iter()
+                .map(|rate| format!("{} {}", rate.from, rate.factor))
+                .collect()
+        };
+        assert_eq!(factors("2023-06-01"), vec!["EUR 1.1"]);
+        assert_eq!(factors("2024-03-01"), vec!["EUR 1.08", "GBP 1.27"]);
+        assert_eq!(factors("2024-07-01"), vec!["EUR 1.08"]);
+        assert!(factors("2022-01-01").is_empty());
+    }
+}
@@ -0,0 +1,181 @@
+use super::facts::FactsFile;
+use super::unit_conversion::ConversionError;
+use std::path::Path;
+
+/// A format the unit registry, the units, systems and facts of a
+/// [`FactsFile`], can be written in and read back from.
+///
+/// Factors and offsets are written as strings such as `"3.28"` or `"5/9"` so
+/// they keep the exact number they were read as, but plain numbers are read
+/// too. Only what differs from a unit's defaults is written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegistryFormat {
+    Json,
+    Toml,
+}
+
+impl RegistryFormat {
+    /// Reads `json` or `toml`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "json" => Some(RegistryFormat::Json),
+            "toml" => Some(RegistryFormat::Toml),
+            _ => None,
+        }
+    }
+
+    /// The format a file's extension names, e.g. `units.toml`, or `None` for
+    /// a plain facts file.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?;
+        Self::from_name(&extension.to_lowercase())
+    }
+}
+
+/// Writes the registry in `format`.
+pub fn to_string(file: &FactsFile, format: RegistryFormat) -> String {
+    match format {
+        RegistryFormat::Json => {
+            serde_json::to_string_pretty(file).expect("a registry is always valid JSON")
+        }
+        RegistryFormat::Toml => toml::to_string(file).expect("a registry is always valid TOML"),
+    }
+}
+
+/// Reads a registry written in `format`. `path`, if given, is recorded as
+/// the source in errors. Every fact is checked as a facts file's would be,
+/// and one that isn't valid, like one with a factor of 0, is a `ParseError`.
+pub fn parse(
+    source: &str,
+    format: RegistryFormat,
+    path: Option<&str>,
+) -> Result<FactsFile, ConversionError> {
+    let parsed = match format {
+        RegistryFormat::Json => serde_json::from_str(source).map_err(|e| e.to_string()),
+        RegistryFormat::Toml => toml::from_str(source).map_err(|e| e.to_string()),
+    };
+    let file: FactsFile = parsed.map_err(|reason| ConversionError::InvalidRegistry {
+        path: path.map(String::from),
+        reason: reason.trim_end().to_string(),
+    })?;
+    for fact in &file.facts {
+        fact.validate()
+            .map_err(|reason| ConversionError::ParseError {
+                input: fact.to_string(),
+                position: 0,
+                reason,
+            })?;
+    }
+    Ok(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::graph::UnitGraph;
+    use super::*;
+
+    #[test]
+    fn round_trips_the_standard_registry() {
+        let graph = UnitGraph::standard();
+        let registry = graph.registry();
+        for format in [RegistryFormat::Json, RegistryFormat::Toml] {
+            let written = to_string(&registry, format);
+            let read = parse(&written, format, None).unwrap();
+            assert_eq!(read, registry, "{:?}", format);
+            let rebuilt = UnitGraph::build(read).unwrap();
+            for (from, to) in [
+                ("m", "in"),
+                ("°C", "degF"),
+                ("KiB", "kbit"),
+                ("N", "lb*ft/hr^2"),
+            ] {
+                assert_eq!(
+                    rebuilt.convert_expr(
+                        100.,
+                        &graph.parse_unit(from).unwrap(),
+                        &graph.parse_unit(to).unwrap()
+                    ),
+                    graph.convert_expr(
+                        100.,
+                        &graph.parse_unit(from).unwrap(),
+                        &graph.parse_unit(to).unwrap()
+                    )
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn reads_hand_written_registries() {
+        let json = r#"{
+            "units": [{ "symbol": "m", "dimension": "L", "prefixes": ["si"] }],
+            "facts": [{ "from": "m", "factor": 3.28, "to": "ft" }]
+        }"#;
+        let toml = r#"
+            [[units]]
+            symbol = "m"
+            dimension = "L"
+            prefixes = ["si"]
+
+            [[facts]]
+            from = "m"
+            factor = "328/100"
+            to = "ft"
+        "#;
+        for (source, format) in [(json, RegistryFormat::Json), (toml, RegistryFormat::Toml)] {
+            let graph = UnitGraph::build(parse(source, format, None).unwrap()).unwrap();
+            assert_eq!(graph.convert(1., "km", "ft"), Some(3280.));
+        }
+
+        let err = parse(
+            r#"{"facts": [{"from": "m"}]}"#,
+            RegistryFormat::Json,
+            Some("u.json"),
+        );
+        assert!(matches!(
+            err,
+            Err(ConversionError::InvalidRegistry { path: Some(_), ref reason })
+                if reason.contains("missing field `factor`")
+        ));
+    }
+
+    #[test]
+    fn checks_the_facts_it_reads() {
+        for (fact, expected) in [
+            (
+                r#"{"from": "a", "factor": 0, "to": "b"}"#,
+                "factor must be positive",
+            ),
+            (
+                r#"{"from": "a", "factor": "-2", "to": "b"}"#,
+                "factor must be positive",
+            ),
+            (
+                r#"{"from": "a", "factor": 2, "to": "a/s"}"#,
+                "cannot be defined in terms of itself",
+            ),
+            (
+                r#"{"from": "a", "factor": 2, "to": "b/s", "offset": 3}"#,
+                "an offset needs a plain unit",
+            ),
+            (
+                r#"{"from": "a", "factor": 2, "uncertainty": -1, "to": "b"}"#,
+                "invalid uncertainty",
+            ),
+        ] {
+            let json = format!(r#"{{"facts": [{}]}}"#, fact);
+            let err = parse(&json, RegistryFormat::Json, None).unwrap_err();
+            assert!(
+                matches!(err, ConversionError::ParseError { ref reason, .. } if reason.contains(expected)),
+                "{} gave {:?}",
+                fact,
+                err
+            );
+        }
+        let toml = "[[facts]]\nfrom = \"a\"\nfactor = 0\nto = \"b\"\n";
+        assert!(matches!(
+            parse(toml, RegistryFormat::Toml, None),
+            Err(ConversionError::ParseError { .. })
+        ));
+    }
+}
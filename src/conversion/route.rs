@@ -0,0 +1,90 @@
+use super::facts::Fact;
+use std::fmt;
+
+/// How to choose among several chains of facts between two units, such as
+/// `m -> ft -> in` and `m -> cm -> in`. Every policy falls back on the fewest
+/// facts to break a tie.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RoutePolicy {
+    /// The fewest facts.
+    #[default]
+    FewestHops,
+    /// The fewest measured facts, those written with an uncertainty.
+    MostExact,
+    /// The smallest relative uncertainty the facts add up to.
+    LeastUncertainty,
+}
+
+impl RoutePolicy {
+    /// Reads `hops`, `exact` or `uncertainty`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "hops" => Some(RoutePolicy::FewestHops),
+            "exact" => Some(RoutePolicy::MostExact),
+            "uncertainty" => Some(RoutePolicy::LeastUncertainty),
+            _ => None,
+        }
+    }
+
+    /// What the policy prefers, as in "the route with the fewest facts".
+    pub fn describe(&self) -> &'static str {
+        match self {
+            RoutePolicy::FewestHops => "the fewest facts",
+            RoutePolicy::MostExact => "the fewest measured facts",
+            RoutePolicy::LeastUncertainty => "the least uncertainty",
+        }
+    }
+
+    // What routes are compared by, smallest first: the policy's own measure,
+    // then the number of facts. Each part only grows along a route, so the
+    // cheapest route can be found one fact at a time.
+    pub(crate) fn cost(&self, hops: usize, measured: usize, variance: f64) -> (f64, usize) {
+        match self {
+            RoutePolicy::FewestHops => (0., hops),
+            RoutePolicy::MostExact => (measured as f64, hops),
+            RoutePolicy::LeastUncertainty => (variance, hops),
+        }
+    }
+}
+
+/// A chain of facts between two units, with what each policy weighs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Route {
+    pub facts: Vec<Fact>,
+    /// How many of the facts are measured.
+    pub measured: usize,
+    /// The relative uncertainty of the facts' combined ratio, to first order.
+    pub relative_uncertainty: f64,
+}
+
+impl Route {
+    pub fn hops(&self) -> usize {
+        self.facts.len()
+    }
+
+    pub(crate) fn cost(&self, policy: RoutePolicy) -> (f64, usize) {
+        policy.cost(
+            self.hops(),
+            self.measured,
+            self.relative_uncertainty.powi(2),
+        )
+    }
+}
+
+/// Writes the facts and their cost, e.g.
+/// `m = 3.28 ± 0.001 ft, ft = 12 in (2 facts, 1 measured, ±3.05e-4)`.
+impl fmt::Display for Route {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let facts: Vec<String> = self.facts.iter().map(Fact::to_string).collect();
+        let plural = if self.hops() == 1 { "" } else { "s" };
+        write!(f, "{} ({} fact{}", facts.join(", "), self.hops(), plural)?;
+        match self.measured {
+            0 => write!(f, ", exact)"),
+            measured => write!(
+                f,
+                ", {} measured, ±{:.2e})",
+                measured, self.relative_uncertainty
+            ),
+        }
+    }
+}
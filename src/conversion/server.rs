@@ -0,0 +1,577 @@
+use super::consistency::ConsistencyCheck;
+use super::facts;
+use super::graph::{GraphSource, UnitGraph};
+use super::number::Number;
+use super::registry::{self, RegistryFormat};
+use super::unit_conversion::{ConversionError, ConversionQuery};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::Read;
+use std::net::SocketAddr;
+use std::panic::{self, AssertUnwindSafe};
+
+/// The largest request body [`Server`] reads, 1 MiB unless
+/// [`Server::with_max_body`] says otherwise. Longer ones get a 413.
+pub const MAX_BODY_BYTES: u64 = 1 << 20;
+
+/// The answer to one request: an HTTP status and a JSON body.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reply {
+    pub status: u16,
+    pub body: Value,
+}
+
+impl Reply {
+    fn ok(body: Value) -> Self {
+        Self { status: 200, body }
+    }
+
+    fn error(status: u16, kind: &str, message: String) -> Self {
+        Self {
+            status,
+            body: json!({ "error": { "kind": kind, "message": message } }),
+        }
+    }
+}
+
+/// An error as `{"error": {"kind": ..., "message": ...}}`, with the fields of
+/// the variant a client is likely to act on, such as an unknown unit's
+/// suggestions. Unknown units and systems are 404s, conversions the facts
+/// can't make are 422s, input or facts that don't parse or contradict each
+/// other are 400s, and a facts file the server can't read is a 500.
+impl From<ConversionError> for Reply {
+    fn from(error: ConversionError) -> Self {
+        let (status, kind) = match &error {
+            ConversionError::UnknownUnit { .. } => (404, "UnknownUnit"),
+            ConversionError::UnknownSystem { .. } => (404, "UnknownSystem"),
+            ConversionError::IncompatibleDimensions { .. } => (422, "IncompatibleDimensions"),
+            ConversionError::NoPath { .. } => (422, "NoPath"),
+            ConversionError::AmbiguousUnit { .. } => (422, "AmbiguousUnit"),
+            ConversionError::AffineInCompound { .. } => (422, "AffineInCompound"),
+            ConversionError::AbsoluteAndDifference { .. } => (422, "AbsoluteAndDifference"),
+            ConversionError::NoUnitInSystem { .. } => (422, "NoUnitInSystem"),
+            ConversionError::ParseError { .. } => (400, "ParseError"),
+            ConversionError::InvalidFact { .. } => (400, "InvalidFact"),
+            ConversionError::InvalidRegistry { .. } => (400, "InvalidRegistry"),
+            ConversionError::InconsistentFacts { .. } => (400, "InconsistentFacts"),
+            ConversionError::DuplicateName { .. } => (400, "DuplicateName"),
+            ConversionError::ConflictingDimensions { .. } => (400, "ConflictingDimensions"),
+            ConversionError::CircularDefinition { .. } => (400, "CircularDefinition"),
+            ConversionError::PrefixCollision { .. } => (400, "PrefixCollision"),
+            ConversionError::ReadFacts { .. } => (500, "ReadFacts"),
+        };
+        let mut reply = Reply::error(status, kind, error.to_string());
+        let details = match error {
+            ConversionError::UnknownUnit { name, suggestions } => {
+                json!({ "name": name, "suggestions": suggestions })
+            }
+            ConversionError::UnknownSystem { name, known } => {
+                json!({ "name": name, "known": known })
+            }
+            ConversionError::IncompatibleDimensions {
+                from,
+                to,
+                from_dimension,
+                to_dimension,
+            } => json!({
+                "from": from,
+                "to": to,
+                "from_dimension": from_dimension.to_string(),
+                "to_dimension": to_dimension.to_string(),
+            }),
+            ConversionError::NoPath { from, to } => json!({ "from": from, "to": to }),
+            ConversionError::AmbiguousUnit { name, candidates } => {
+                json!({ "name": name, "candidates": candidates })
+            }
+            ConversionError::ParseError {
+                input,
+                position,
+                reason,
+            } => json!({ "input": input, "position": position, "reason": reason }),
+            ConversionError::InvalidFact {
+                line, text, reason, ..
+            } => json!({ "line": line, "text": text, "reason": reason }),
+            _ => json!({}),
+        };
+        if let (Value::Object(error), Value::Object(details)) = (&mut reply.body["error"], details)
+        {
+            error.extend(details);
+        }
+        reply
+    }
+}
+
+// One conversion, as `GET /convert` takes it in its query string and
+// `POST /batch` in its array. The value may be a number or a string such as
+// `"5/9"`.
+#[derive(Debug, Deserialize)]
+struct ConvertRequest {
+    value: Number,
+    from: String,
+    to: String,
+}
+
+/// The conversion engine behind `serve`. It answers a small JSON API against
+/// a graph that stays loaded between requests:
+///
+/// - `GET /convert?value=2&from=m&to=in` converts one value.
+/// - `POST /batch` converts a JSON array of `{"value", "from", "to"}`
+///   objects, answering each with its result or its error.
+/// - `GET /units` lists the units by dimension.
+/// - `POST /facts` replaces the facts with the body, a facts file or a JSON
+///   or TOML registry by its `Content-Type`, or with an empty body reloads the
+///   facts the service started with. Either way the rates of its source are
+///   added again.
+#[derive(Debug, Clone)]
+pub struct Service {
+    graph: UnitGraph,
+    source: GraphSource,
+    check: ConsistencyCheck,
+}
+
+impl Service {
+    /// A service answering with `graph`, loaded from `source`, checking new
+    /// facts with `check`. An empty `POST /facts` loads `source` again.
+    pub fn new(graph: UnitGraph, source: GraphSource, check: ConsistencyCheck) -> Self {
+        Self {
+            graph,
+            source,
+            check,
+        }
+    }
+
+    pub fn graph(&self) -> &UnitGraph {
+        &self.graph
+    }
+
+    /// Answers one request. `target` is the path with its query string, e.g.
+    /// `/convert?value=2&from=m&to=in`.
+    pub fn handle(
+        &mut self,
+        method: &str,
+        target: &str,
+        content_type: Option<&str>,
+        body: &str,
+    ) -> Reply {
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+        let reply = match (method, path) {
+            ("GET", "/convert") => self.convert_query(query),
+            ("POST", "/batch") => self.batch(body),
+            ("GET", "/units") => Ok(self.units()),
+            ("POST", "/facts") => self.reload(content_type, body),
+            (_, "/convert" | "/batch" | "/units" | "/facts") => {
+                return Reply::error(
+                    405,
+                    "MethodNotAllowed",
+                    format!("{} {} is not supported", method, path),
+                )
+            }
+            _ => return Reply::error(404, "NotFound", format!("Nothing at {}", path)),
+        };
+        reply.unwrap_or_else(|reply| reply)
+    }
+
+    fn convert_query(&self, query: &str) -> Result<Reply, Reply> {
+        let params: HashMap<String, String> = query
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .map(|(key, value)| (decode(key), decode(value)))
+            .collect();
+        let param = |name: &str| {
+            params.get(name).ok_or_else(|| {
+                Reply::error(
+                    400,
+                    "BadRequest",
+                    format!("missing the `{}` parameter", name),
+                )
+            })
+        };
+        let request = ConvertRequest {
+            value: param("value")?.parse().map_err(Reply::from)?,
+            from: param("from")?.clone(),
+            to: param("to")?.clone(),
+        };
+        self.convert(&request).map(Reply::ok).map_err(Reply::from)
+    }
+
+    fn batch(&self, body: &str) -> Result<Reply, Reply> {
+        let requests: Vec<ConvertRequest> = serde_json::from_str(body).map_err(|e| {
+            Reply::error(
+                400,
+                "BadRequest",
+                format!("expected an array of conversions: {}", e),
+            )
+        })?;
+        let results: Vec<Value> = requests
+            .iter()
+            .map(|request| match self.convert(request) {
+                Ok(result) => result,
+                Err(e) => Reply::from(e).body,
+            })
+            .collect();
+        Ok(Reply::ok(json!({ "results": results })))
+    }
+
+    fn convert(&self, request: &ConvertRequest) -> Result<Value, ConversionError> {
+        let query = ConversionQuery::with_graph(
+            &self.graph,
+            request.value.clone(),
+            &request.from,
+            &request.to,
+        )?;
+        Ok(json!({
+            "value": request.value.value(),
+            "from": query.from().to_string(),
+            "to": query.to().to_string(),
+            "result": query.convert_units()?,
+        }))
+    }
+
+    fn units(&self) -> Reply {
+        let dimensions: Vec<Value> = self
+            .graph
+            .units_by_dimension()
+            .into_iter()
+            .map(|(dimension, symbols)| {
+                json!({ "dimension": dimension.to_string(), "symbols": symbols })
+            })
+            .collect();
+        Reply::ok(json!({ "units": dimensions }))
+    }
+
+    fn reload(&mut self, content_type: Option<&str>, body: &str) -> Result<Reply, Reply> {
+        let graph = if body.trim().is_empty() {
+            self.source.load()
+        } else {
+            // `application/json` or `application/toml`, else a facts file.
+            let format = content_type
+                .and_then(|content_type| content_type.split(';').next()?.split_once('/'))
+                .and_then(|(_, subtype)| RegistryFormat::from_name(subtype.trim()));
+            match format {
+                Some(format) => registry::parse(body, format, None),
+                None => facts::parse_facts(body),
+            }
+            .and_then(UnitGraph::build)
+            .and_then(|graph| self.source.add_rates(graph))
+        };
+        let graph = graph.map_err(Reply::from)?;
+        let warnings = self.check.verify(&graph).map_err(Reply::from)?;
+        let warnings: Vec<String> = warnings.iter().map(ToString::to_string).collect();
+        self.graph = graph;
+        Ok(Reply::ok(json!({
+            "units": self.graph.units().count(),
+            "facts": self.graph.registry().facts.len(),
+            "warnings": warnings,
+        })))
+    }
+}
+
+/// Serves a [`Service`] over HTTP, one request at a time. A request whose
+/// body is too long is refused with a 413, and one that panics the service
+/// gets a 500 without stopping the server.
+pub struct Server {
+    http: tiny_http::Server,
+    service: Service,
+    max_body: u64,
+}
+
+impl Server {
+    /// Listens on `address`, e.g. `127.0.0.1:8080`, or port 0 for any free
+    /// port.
+    pub fn bind(
+        address: &str,
+        service: Service,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(Self {
+            http: tiny_http::Server::http(address)?,
+            service,
+            max_body: MAX_BODY_BYTES,
+        })
+    }
+
+    /// Refuses request bodies longer than `bytes` instead of
+    /// [`MAX_BODY_BYTES`].
+    pub fn with_max_body(self, bytes: u64) -> Self {
+        Self {
+            max_body: bytes,
+            ..self
+        }
+    }
+
+    /// The address the server listens on.
+    pub fn address(&self) -> Option<SocketAddr> {
+        self.http.server_addr().to_ip()
+    }
+
+    /// Answers requests for as long as the listener lasts.
+    pub fn run(self) {
+        let Self {
+            http,
+            mut service,
+            max_body,
+        } = self;
+        let too_large = || {
+            Reply::error(
+                413,
+                "PayloadTooLarge",
+                format!("the request body is over {} bytes", max_body),
+            )
+        };
+        for mut request in http.incoming_requests() {
+            let mut body = String::new();
+            let declared = request.body_length().map_or(0, |length| length as u64);
+            let read = if declared > max_body {
+                Ok(None)
+            } else {
+                // A body without a length is cut off one byte past the limit,
+                // which is enough to tell it's too long.
+                request
+                    .as_reader()
+                    .take(max_body + 1)
+                    .read_to_string(&mut body)
+                    .map(|length| Some(length as u64).filter(|&length| length <= max_body))
+            };
+            let reply = match read {
+                Ok(Some(_)) => {
+                    let content_type = request
+                        .headers()
+                        .iter()
+                        .find(|header| header.field.equiv("Content-Type"))
+                        .map(|header| header.value.to_string());
+                    let (method, url) = (request.method().as_str(), request.url());
+                    panic::catch_unwind(AssertUnwindSafe(|| {
+                        service.handle(method, url, content_type.as_deref(), &body)
+                    }))
+                    .unwrap_or_else(|_| {
+                        Reply::error(
+                            500,
+                            "InternalError",
+                            format!("the server failed while answering {} {}", method, url),
+                        )
+                    })
+                }
+                Ok(None) => too_large(),
+                Err(e) => Reply::error(400, "BadRequest", format!("unreadable body: {}", e)),
+            };
+            let response = tiny_http::Response::from_string(reply.body.to_string())
+                .with_status_code(reply.status)
+                .with_header(
+                    tiny_http::Header::from_bytes("Content-Type", "application/json")
+                        .expect("a valid header"),
+                );
+            // A client that hung up has nobody to tell.
+            let _ = request.respond(response);
+        }
+    }
+}
+
+// Decodes a query string component: `+` is a space and `%2F` a `/`.
+fn decode(s: &str) -> String {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut i = 0;
+    while i < s.len() {
+        let escaped = s
+            .get(i + 1..i + 3)
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (s.as_bytes()[i], escaped) {
+            (b'%', Some(byte)) => {
+                bytes.push(byte);
+                i += 3;
+            }
+            (b'+', _) => {
+                bytes.push(b' ');
+                i += 1;
+            }
+            (byte, _) => {
+                bytes.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+
+    fn service() -> Service {
+        Service::new(
+            UnitGraph::standard(),
+            GraphSource::default(),
+            ConsistencyCheck::default(),
+        )
+    }
+
+    #[test]
+    fn answers_the_json_api() {
+        let mut service = service();
+        let reply = service.handle("GET", "/convert?value=2&from=m&to=inches", None, "");
+        assert_eq!(
+            reply,
+            Reply::ok(json!({ "value": 2., "from": "m", "to": "in", "result": 78.72 }))
+        );
+        let reply = service.handle("GET", "/convert?value=60&from=mi%2Fhr&to=m/s", None, "");
+        assert_eq!(reply.body["to"], "m/s");
+
+        let reply = service.handle("GET", "/convert?value=1&from=fet&to=in", None, "");
+        assert_eq!(reply.status, 404);
+        assert_eq!(reply.body["error"]["kind"], "UnknownUnit");
+        assert_eq!(reply.body["error"]["suggestions"], json!(["feet", "ft"]));
+        let reply = service.handle("GET", "/convert?value=1&from=m&to=s", None, "");
+        assert_eq!(reply.status, 422);
+        assert_eq!(reply.body["error"]["to_dimension"], "T");
+        let reply = service.handle("GET", "/convert?value=two&from=m&to=in", None, "");
+        assert_eq!(reply.body["error"]["kind"], "ParseError");
+        let reply = service.handle("GET", "/convert?value=1&from=m", None, "");
+        assert_eq!(reply.status, 400);
+        // Refused before the exact value, ten to the hundred millionth, is
+        // worked out, which would hold up every other client.
+        let reply = service.handle("GET", "/convert?value=1e99999999&from=m&to=in", None, "");
+        assert_eq!(reply.status, 400);
+        assert_eq!(
+            reply.body["error"]["reason"],
+            "the exponent is out of range"
+        );
+        for (from, to) in [
+            ("m%5E2147483647*m", "m"),
+            ("1/m%5E-2147483648", "1/m"),
+            ("N%5E2000000000", "N"),
+        ] {
+            let target = format!("/convert?value=1&from={}&to={}", from, to);
+            let reply = service.handle("GET", &target, None, "");
+            assert_eq!(reply.status, 400);
+            assert_eq!(reply.body["error"]["reason"], "the power is out of range");
+        }
+        assert_eq!(service.handle("DELETE", "/units", None, "").status, 405);
+        assert_eq!(service.handle("GET", "/", None, "").status, 404);
+
+        let batch = r#"[{"value": 2, "from": "m", "to": "in"}, {"value": "5/9", "from": "hr", "to": "lb"}]"#;
+        let reply = service.handle("POST", "/batch", None, batch);
+        assert_eq!(reply.status, 200);
+        assert_eq!(reply.body["results"][0]["result"], 78.72);
+        assert_eq!(
+            reply.body["results"][1]["error"]["kind"],
+            "IncompatibleDimensions"
+        );
+        assert_eq!(service.handle("POST", "/batch", None, "{").status, 400);
+        let batch = r#"[{"value": "1e-99999999", "from": "m", "to": "in"}]"#;
+        assert_eq!(service.handle("POST", "/batch", None, batch).status, 400);
+
+        let reply = service.handle("GET", "/units", None, "");
+        assert_eq!(reply.body["units"][0]["dimension"], "L");
+        assert_eq!(reply.body["units"][0]["symbols"][0], "m");
+    }
+
+    #[test]
+    fn replaces_and_reloads_the_facts() {
+        let mut service = service();
+        let reply = service.handle("POST", "/facts", Some("text/plain"), "furlong = 660 ft\n");
+        assert_eq!(reply.status, 200);
+        let reply = service.handle("GET", "/convert?value=1&from=furlong&to=ft", None, "");
+        assert_eq!(reply.body["result"], 660.);
+        assert_eq!(service.graph().convert(1., "m", "ft"), None);
+
+        let registry = r#"{"facts": [{"from": "yd", "factor": 3, "to": "ft"}]}"#;
+        let reply = service.handle(
+            "POST",
+            "/facts",
+            Some("application/json; charset=utf-8"),
+            registry,
+        );
+        assert_eq!(reply.body["facts"], 1);
+        let reply = service.handle("POST", "/facts", None, "m = 3.28\n");
+        assert_eq!(reply.body["error"]["kind"], "InvalidFact");
+        assert!(service.graph().contains("yd"));
+
+        let reply = service.handle("POST", "/facts", None, "");
+        assert_eq!(reply.status, 200);
+        assert!(service.graph().contains("m"));
+    }
+
+    #[test]
+    fn keeps_the_rates_across_reloads() {
+        let source = GraphSource {
+            facts: None,
+            rates: Some("facts/example_rates.csv".to_string()),
+            as_of: NaiveDate::from_ymd_opt(2023, 5, 1),
+        };
+        let mut service = Service::new(source.load().unwrap(), source, ConsistencyCheck::default());
+        let euros = "/convert?value=100&from=EUR&to=USD";
+        let before = service.handle("GET", euros, None, "");
+        assert!((before.body["result"].as_f64().unwrap() - 110.).abs() < 1e-9);
+
+        assert_eq!(service.handle("POST", "/facts", None, "").status, 200);
+        assert_eq!(service.handle("GET", euros, None, ""), before);
+        let reply = service.handle("POST", "/facts", None, "furlong = 660 ft\n");
+        assert_eq!(reply.status, 200);
+        assert_eq!(service.handle("GET", euros, None, ""), before);
+    }
+
+    #[test]
+    fn serves_over_a_local_port() {
+        let server = Server::bind("127.0.0.1:0", service()).unwrap();
+        let address = server.address().unwrap();
+        std::thread::spawn(move || server.run());
+
+        let mut stream = TcpStream::connect(address).unwrap();
+        write!(
+            stream,
+            "GET /convert?value=100&from=%C2%B0C&to=K HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n"
+        )
+        .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+        assert!(response.contains("application/json"));
+        let body = response.split("\r\n\r\n").nth(1).unwrap();
+        let body: Value = serde_json::from_str(body).unwrap();
+        assert_eq!(body["result"], 373.15);
+    }
+
+    #[test]
+    fn refuses_oversized_bodies() {
+        let server = Server::bind("127.0.0.1:0", service())
+            .unwrap()
+            .with_max_body(64);
+        let address = server.address().unwrap();
+        std::thread::spawn(move || server.run());
+
+        let post = |body: &str| {
+            let mut stream = TcpStream::connect(address).unwrap();
+            write!(
+                stream,
+                "POST /batch HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            )
+            .unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        };
+        let batch = r#"[{"value": 2, "from": "m", "to": "in"}]"#;
+        assert!(post(batch).starts_with("HTTP/1.1 200"));
+        let oversized = format!("[{}]", vec![batch; 4].join(","));
+        let response = post(&oversized);
+        assert!(response.starts_with("HTTP/1.1 413"), "{}", response);
+        assert!(response.contains("PayloadTooLarge"));
+
+        // Without a length the body is read up to the limit and no further.
+        let mut stream = TcpStream::connect(address).unwrap();
+        write!(
+            stream,
+            "POST /batch HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\nConnection: close\r\n\r\n{:x}\r\n{}\r\n0\r\n\r\n",
+            oversized.len(),
+            oversized
+        )
+        .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 413"), "{}", response);
+    }
+}
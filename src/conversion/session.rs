@@ -0,0 +1,145 @@
+use super::consistency::ConsistencyCheck;
+use super::facts;
+use super::graph::UnitGraph;
+use super::query::{self, AnswerOptions};
+
+const HELP: &str = "\
+<value> <unit> = ? <unit>   convert, e.g. `2 m = ? in`
+define <fact>              add a fact, e.g. `define furlong = 660 ft`
+define unit <symbol> ...   declare a unit, as in a facts file
+units                      list the units by dimension
+help                       show this message
+quit                       leave (or Ctrl-D)";
+
+/// The state of an interactive session: a graph that stays loaded between
+/// queries and grows with every `define`.
+#[derive(Debug, Clone)]
+pub struct Session {
+    graph: UnitGraph,
+    options: AnswerOptions,
+    check: ConsistencyCheck,
+}
+
+impl Session {
+    /// A session answering with `options`, checking defined facts with `check`.
+    pub fn new(graph: UnitGraph, options: AnswerOptions, check: ConsistencyCheck) -> Self {
+        Self {
+            graph,
+            options,
+            check,
+        }
+    }
+
+    pub fn graph(&self) -> &UnitGraph {
+        &self.graph
+    }
+
+    /// Runs one line of input and returns what to print: an answer, the unit
+    /// list, or an error message.
+    pub fn eval(&mut self, line: &str) -> String {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        match line.split_once(char::is_whitespace) {
+            _ if line.is_empty() => String::new(),
+            _ if line == "help" => HELP.to_string(),
+            _ if line == "units" => self.list_units(),
+            Some(("define", definition)) => self.define(definition.trim()),
+            _ => query::answer_line(&self.graph, line, self.options),
+        }
+    }
+
+    /// The names that could complete the word ending at byte `pos` of `line`,
+    /// and where that word starts.
+    pub fn complete(&self, line: &str, pos: usize) -> (usize, Vec<String>) {
+        let before = &line[..pos];
+        let start = before
+            .rfind(|c: char| c.is_whitespace() || matches!(c, '*' | '·' | '⋅' | '/' | '^' | '='))
+            .map_or(0, |i| {
+                i + before[i..].chars().next().map_or(1, char::len_utf8)
+            });
+        let word = &before[start..];
+        if word.is_empty() || word.starts_with(|c: char| c.is_ascii_digit()) {
+            return (start, Vec::new());
+        }
+        let names = self.graph.names();
+        let matches = names.into_iter().filter(|name| name.starts_with(word));
+        (start, matches.collect())
+    }
+
+    fn define(&mut self, definition: &str) -> String {
+        let before = self.graph.inconsistencies(self.check.tolerance);
+        let mut graph = self.graph.clone();
+        let defined = facts::parse_facts(definition)
+            .and_then(|file| graph.define(file))
+            .and_then(|()| self.check.verify(&graph));
+        match defined {
+            Ok(found) => {
+                self.graph = graph;
+                let mut lines = vec![format!("defined {}", definition)];
+                lines.extend(
+                    found
+                        .into_iter()
+                        .filter(|inconsistency| !before.contains(inconsistency))
+                        .map(|inconsistency| format!("warning: {}", inconsistency)),
+                );
+                lines.join("\n")
+            }
+            Err(e) => format!("error: {}", e),
+        }
+    }
+
+    fn list_units(&self) -> String {
+        let lines: Vec<String> = self
+            .graph
+            .units_by_dimension()
+            .into_iter()
+            .map(|(dimension, symbols)| format!("{}: {}", dimension, symbols.join(", ")))
+            .collect();
+        lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session() -> Session {
+        Session::new(
+            UnitGraph::standard(),
+            AnswerOptions::default(),
+            ConsistencyCheck::default(),
+        )
+    }
+
+    #[test]
+    fn defines_facts_between_queries() {
+        let mut session = session();
+        assert_eq!(session.eval("2 m = ? in"), "78.72");
+        assert!(session
+            .eval("1 furlong = ? ft")
+            .starts_with("error: Unknown unit"));
+        assert_eq!(
+            session.eval("define furlong = 660 ft"),
+            "defined furlong = 660 ft"
+        );
+        assert_eq!(session.eval("2 furlong = ? ft"), "1320");
+        assert!(session
+            .eval("define m = 40 in")
+            .starts_with("defined m = 40 in\nwarning: [m = 3.28 ft"));
+        assert!(session.eval("define x = 1 y/").starts_with("error: "));
+        assert!(session.eval("units").contains("L: m, ft, in, mi"));
+    }
+
+    #[test]
+    fn completes_unit_names() {
+        let session = session();
+        let (start, names) = session.complete("2 me", 4);
+        assert_eq!(start, 2);
+        assert_eq!(names, vec!["meter", "meters", "metre", "metres"]);
+        let (start, names) = session.complete("1 km/ho", 7);
+        assert_eq!(
+            (start, names),
+            (5, vec!["hour".to_string(), "hours".to_string()])
+        );
+        assert!(session.complete("2", 1).1.is_empty());
+    }
+}
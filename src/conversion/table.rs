@@ -0,0 +1,184 @@
+use super::format::Format;
+use super::graph::UnitGraph;
+use super::number::Number;
+use super::quantity::Quantity;
+use super::unit_conversion::ConversionError;
+use std::fmt;
+
+/// A row of a table that couldn't be converted, and why.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RowError {
+    pub line: usize,
+    pub error: ConversionError,
+}
+
+impl fmt::Display for RowError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.error)
+    }
+}
+
+/// A table converted by [`convert_table`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConvertedTable {
+    /// The header and every converted row, as CSV.
+    pub csv: String,
+    /// How many rows were converted.
+    pub converted: usize,
+    /// The rows left out because they couldn't be converted.
+    pub failures: Vec<RowError>,
+}
+
+/// Converts a CSV of measurements to `system`. The header must name a `value`
+/// and a `unit` column; every row's value is converted to the most readable
+/// unit of `system` (see [`UnitGraph::to_best`]) and written with `format`,
+/// its unit with symbols. Other columns are copied as they were, and lines
+/// starting with `#` are skipped.
+///
+/// A row that can't be converted, such as one with an unknown unit or a
+/// dimension `system` has no unit for, is left out and reported with its line
+/// number rather than stopping the run. Only an unknown system or a missing
+/// column fails the whole table.
+pub fn convert_table(
+    graph: &UnitGraph,
+    source: &str,
+    file: Option<&str>,
+    system: &str,
+    format: &Format,
+) -> Result<ConvertedTable, ConversionError> {
+    let system = graph.system(system)?;
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .flexible(true)
+        .has_headers(false)
+        .from_reader(source.as_bytes());
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    let mut columns = None;
+    let mut converted = 0;
+    let mut failures = Vec::new();
+    for record in reader.records() {
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                failures.push(RowError {
+                    line: e.position().map_or(0, |p| p.line() as usize),
+                    error: ConversionError::ParseError {
+                        input: String::new(),
+                        position: 0,
+                        reason: e.to_string(),
+                    },
+                });
+                continue;
+            }
+        };
+        // Comments are skipped here rather than by the reader, which would
+        // leave them out of the line numbers.
+        if record.get(0).is_some_and(|first| first.starts_with('#')) {
+            continue;
+        }
+        let line = record.position().map_or(0, |p| p.line() as usize);
+        let Some((value_column, unit_column)) = columns else {
+            let find = |name: &str| record.iter().position(|c| c.eq_ignore_ascii_case(name));
+            let (Some(value), Some(unit)) = (find("value"), find("unit")) else {
+                return Err(ConversionError::InvalidFact {
+                    path: file.map(String::from),
+                    line,
+                    text: record.iter().collect::<Vec<_>>().join(","),
+                    reason: "expected a `value` and a `unit` column".to_string(),
+                });
+            };
+            columns = Some((value, unit));
+            write_row(&mut writer, record.iter());
+            continue;
+        };
+
+        let field = |column: usize| record.get(column).unwrap_or_default();
+        let best = field(value_column)
+            .parse::<Number>()
+            .and_then(|value| Quantity::with_graph(graph, value.value(), field(unit_column)))
+            .and_then(|quantity| quantity.to_best(system));
+        match best {
+            Ok(best) => {
+                let value = format.number(best.value());
+                let unit = best.unit().to_string();
+                write_row(
+                    &mut writer,
+                    record.iter().enumerate().map(|(column, text)| {
+                        if column == value_column {
+                            value.as_str()
+                        } else if column == unit_column {
+                            unit.as_str()
+                        } else {
+                            text
+                        }
+                    }),
+                );
+                converted += 1;
+            }
+            Err(error) => failures.push(RowError { line, error }),
+        }
+    }
+    let csv = writer
+        .into_inner()
+        .map(|bytes| String::from_utf8_lossy(&bytes).into_owned())
+        .expect("writing CSV to memory can't fail");
+    Ok(ConvertedTable {
+        csv,
+        converted,
+        failures,
+    })
+}
+
+fn write_row<'r>(writer: &mut csv::Writer<Vec<u8>>, fields: impl Iterator<Item = &'r str>) {
+    writer
+        .write_record(fields)
+        .expect("writing CSV to memory can't fail");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TABLE: &str = "\
+# measurements
+name,value,unit
+desk,1.5,m
+road,100000,in
+box,2,fet
+tank,2,gal
+speed,3,m/s
+";
+
+    #[test]
+    fn converts_rows_and_reports_failures() {
+        let graph = UnitGraph::standard();
+        let format = Format::default();
+        let table = convert_table(&graph, TABLE, None, "imperial", &format).unwrap();
+        assert_eq!(table.converted, 3);
+        let lines: Vec<&str> = table.csv.lines().collect();
+        assert_eq!(lines[0], "name,value,unit");
+        assert!(lines[1].starts_with("desk,4.92,ft"));
+        assert!(lines[2].starts_with("road,1.578"));
+        assert!(lines[3].starts_with("tank,1.665"));
+        let failed: Vec<usize> = table.failures.iter().map(|f| f.line).collect();
+        assert_eq!(failed, [5, 7]);
+        assert!(table.failures[0]
+            .to_string()
+            .starts_with("line 5: Unknown unit `fet`"));
+
+        let us = convert_table(&graph, TABLE, None, "US", &format).unwrap();
+        assert!(us.csv.contains("tank,2,gal"));
+    }
+
+    #[test]
+    fn needs_a_known_system_and_columns() {
+        let graph = UnitGraph::standard();
+        let format = Format::default();
+        assert!(matches!(
+            convert_table(&graph, TABLE, None, "cubits", &format),
+            Err(ConversionError::UnknownSystem { .. })
+        ));
+        let err = convert_table(&graph, "value,units\n1,m\n", None, "si", &format).unwrap_err();
+        assert!(matches!(err, ConversionError::InvalidFact { line: 1, .. }));
+    }
+}
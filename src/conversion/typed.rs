@@ -0,0 +1,371 @@
+//! Quantities whose unit is part of their type, for code that knows its
+//! units when it's written. Each dimension is its own type, so mixing them up
+//! doesn't compile, and conversions between units of one dimension go through
+//! `TryFrom`, failing only if a unit isn't one the standard facts know:
+//!
+//! ```
+//! use jane_street_mock_interview::conversion::typed::*;
+//!
+//! let height = Length::<Feet>::new(5.) + Length::<Inches>::new(11.).try_into()?;
+//! let height: Length<Inches> = height.try_into()?;
+//! assert_eq!(height.value(), 71.);
+//! # Ok::<(), jane_street_mock_interview::conversion::unit_conversion::ConversionError>(())
+//! ```
+//!
+//! ```compile_fail
+//! use jane_street_mock_interview::conversion::typed::*;
+//!
+//! let _ = Length::<Meters>::new(2.) + Duration::<Minutes>::new(3.);
+//! ```
+//!
+//! The types are declared with [`units!`](crate::units), which also declares
+//! more of them outside this crate.
+
+use super::compiled::CompiledGraph;
+use super::expression::UnitExpr;
+use super::unit_conversion::{standard_graph, ConversionError};
+use std::sync::OnceLock;
+
+/// A unit a typed quantity can be in, known by its symbol in the standard
+/// facts.
+pub trait Unit {
+    const SYMBOL: &'static str;
+}
+
+/// Converts `value` from unit `A` to unit `B` with the standard facts. The
+/// `TryFrom` impls [`units!`](crate::units) generates come here, and fail if
+/// either symbol isn't a unit of the standard facts or the two measure
+/// different dimensions.
+pub fn convert<A: Unit, B: Unit>(value: f64) -> Result<f64, ConversionError> {
+    let table = standard_table();
+    let converted = match (table.id(A::SYMBOL), table.id(B::SYMBOL)) {
+        (Some(from), Some(to)) => table.convert(value, from, to),
+        _ => None,
+    };
+    match converted {
+        Some(converted) => Ok(converted),
+        // The graph says why not.
+        None => standard_graph().convert_expr(
+            value,
+            &UnitExpr::unit(A::SYMBOL),
+            &UnitExpr::unit(B::SYMBOL),
+        ),
+    }
+}
+
+fn standard_table() -> &'static CompiledGraph {
+    static TABLE: OnceLock<CompiledGraph> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        standard_graph()
+            .compile()
+            .expect("the standard facts compile")
+    })
+}
+
+/// Declares a dimension as a type generic over its units, and a type for
+/// each unit named by its symbol in the standard facts:
+///
+/// ```
+/// # use jane_street_mock_interview::units;
+/// units! {
+///     /// A length.
+///     Length(LengthUnit) { Meters = "m", Feet = "ft" }
+/// }
+/// # assert!((Length::<Meters>::new(1.).to::<Feet>().unwrap().value() - 3.28).abs() < 1e-9);
+/// # assert_eq!(Length::<Meters>::SYMBOLS, ["m", "ft"]);
+/// ```
+///
+/// declares `Length<U: LengthUnit>` and the units `Meters` and `Feet`.
+/// Quantities of one unit add, subtract and scale by `f64`; converting to
+/// another unit of the dimension is `try_into()` or `to::<Feet>()`, and
+/// conversions to and from [`Quantity`](crate::conversion::quantity::Quantity)
+/// bridge to the string-based API. Conversions fail with a
+/// [`ConversionError`] if a symbol isn't a unit of the standard facts or the
+/// units measure different dimensions; `SYMBOLS` lists every unit of the
+/// dimension so a test can check them.
+#[macro_export]
+macro_rules! units {
+    (@from $dimension:ident;) => {};
+    (@from $dimension:ident; $first:ident $(, $rest:ident)*) => {
+        $(
+            impl ::std::convert::TryFrom<$dimension<$first>> for $dimension<$rest> {
+                type Error = $crate::conversion::unit_conversion::ConversionError;
+
+                fn try_from(
+                    quantity: $dimension<$first>,
+                ) -> ::std::result::Result<Self, Self::Error> {
+                    quantity.to()
+                }
+            }
+
+            impl ::std::convert::TryFrom<$dimension<$rest>> for $dimension<$first> {
+                type Error = $crate::conversion::unit_conversion::ConversionError;
+
+                fn try_from(
+                    quantity: $dimension<$rest>,
+                ) -> ::std::result::Result<Self, Self::Error> {
+                    quantity.to()
+                }
+            }
+        )*
+        $crate::units!(@from $dimension; $($rest),*);
+    };
+    ($(
+        $(#[$meta:meta])*
+        $dimension:ident($kind:ident) {
+            $($(#[$unit_meta:meta])* $unit:ident = $symbol:literal),+ $(,)?
+        }
+    )*) => {$(
+        #[doc = concat!("A unit of [`", stringify!($dimension), "`].")]
+        pub trait $kind: $crate::conversion::typed::Unit {}
+
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, Default, PartialEq, PartialOrd)]
+        pub struct $dimension<U: $kind> {
+            value: f64,
+            unit: ::std::marker::PhantomData<U>,
+        }
+
+        impl<U: $kind> $dimension<U> {
+            /// The symbols of every unit of the dimension, as declared.
+            pub const SYMBOLS: &'static [&'static str] = &[$($symbol),+];
+
+            pub fn new(value: f64) -> Self {
+                Self {
+                    value,
+                    unit: ::std::marker::PhantomData,
+                }
+            }
+
+            pub fn value(&self) -> f64 {
+                self.value
+            }
+
+            /// Converts to another unit of the same dimension, like
+            /// `try_into()` but naming the unit.
+            pub fn to<V: $kind>(
+                self,
+            ) -> ::std::result::Result<
+                $dimension<V>,
+                $crate::conversion::unit_conversion::ConversionError,
+            > {
+                $crate::conversion::typed::convert::<U, V>(self.value).map($dimension::new)
+            }
+        }
+
+        impl<U: $kind> ::std::ops::Add for $dimension<U> {
+            type Output = Self;
+
+            fn add(self, other: Self) -> Self {
+                Self::new(self.value + other.value)
+            }
+        }
+
+        impl<U: $kind> ::std::ops::Sub for $dimension<U> {
+            type Output = Self;
+
+            fn sub(self, other: Self) -> Self {
+                Self::new(self.value - other.value)
+            }
+        }
+
+        impl<U: $kind> ::std::ops::Neg for $dimension<U> {
+            type Output = Self;
+
+            fn neg(self) -> Self {
+                Self::new(-self.value)
+            }
+        }
+
+        impl<U: $kind> ::std::ops::Mul<f64> for $dimension<U> {
+            type Output = Self;
+
+            fn mul(self, factor: f64) -> Self {
+                Self::new(self.value * factor)
+            }
+        }
+
+        impl<U: $kind> ::std::ops::Div<f64> for $dimension<U> {
+            type Output = Self;
+
+            fn div(self, divisor: f64) -> Self {
+                Self::new(self.value / divisor)
+            }
+        }
+
+        impl<U: $kind> ::std::fmt::Display for $dimension<U> {
+            fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+                write!(
+                    f,
+                    "{} {}",
+                    self.value,
+                    <U as $crate::conversion::typed::Unit>::SYMBOL
+                )
+            }
+        }
+
+        /// Fails if the unit isn't one of the standard facts.
+        impl<U: $kind> ::std::convert::TryFrom<$dimension<U>>
+            for $crate::conversion::quantity::Quantity<'static>
+        {
+            type Error = $crate::conversion::unit_conversion::ConversionError;
+
+            fn try_from(
+                quantity: $dimension<U>,
+            ) -> ::std::result::Result<Self, Self::Error> {
+                Self::new(
+                    quantity.value,
+                    <U as $crate::conversion::typed::Unit>::SYMBOL,
+                )
+            }
+        }
+
+        /// Fails if the quantity measures another dimension.
+        impl<U: $kind> ::std::convert::TryFrom<$crate::conversion::quantity::Quantity<'_>>
+            for $dimension<U>
+        {
+            type Error = $crate::conversion::unit_conversion::ConversionError;
+
+            fn try_from(
+                quantity: $crate::conversion::quantity::Quantity<'_>,
+            ) -> ::std::result::Result<Self, Self::Error> {
+                let symbol = <U as $crate::conversion::typed::Unit>::SYMBOL;
+                Ok(Self::new(quantity.to(symbol)?.value()))
+            }
+        }
+
+        $(
+            $(#[$unit_meta])*
+            #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+            pub struct $unit;
+
+            impl $crate::conversion::typed::Unit for $unit {
+                const SYMBOL: &'static str = $symbol;
+            }
+
+            impl $kind for $unit {}
+        )+
+
+        $crate::units!(@from $dimension; $($unit),+);
+    )*};
+}
+
+units! {
+    /// A length, e.g. `Length::<Meters>::new(2.)`.
+    Length(LengthUnit) {
+        Meters = "m",
+        Kilometers = "km",
+        Centimeters = "cm",
+        Millimeters = "mm",
+        Feet = "ft",
+        Inches = "in",
+        Miles = "mi",
+    }
+
+    /// A span of time, e.g. `Duration::<Minutes>::new(90.)`.
+    Duration(DurationUnit) {
+        Seconds = "s",
+        Milliseconds = "ms",
+        Minutes = "min",
+        Hours = "hr",
+    }
+
+    /// A mass, e.g. `Mass::<Pounds>::new(3.)`.
+    Mass(MassUnit) {
+        Kilograms = "kg",
+        Grams = "g",
+        Pounds = "lb",
+    }
+
+    /// An amount of information, e.g. `Information::<Kibibytes>::new(4.)`.
+    Information(InformationUnit) {
+        Bits = "bit",
+        Bytes = "B",
+        Kilobytes = "kB",
+        Kibibytes = "KiB",
+        Megabytes = "MB",
+        Mebibytes = "MiB",
+    }
+
+    /// A volume, e.g. `Volume::<Gallons>::new(2.)`.
+    Volume(VolumeUnit) {
+        Liters = "L",
+        Milliliters = "mL",
+        Gallons = "gal",
+        ImperialGallons = "imp_gal",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::quantity::Quantity;
+    use super::*;
+
+    #[test]
+    fn converts_between_typed_units() {
+        let length: Length<Inches> = Length::<Meters>::new(2.).try_into().unwrap();
+        assert!((length.value() - 78.72).abs() < 1e-9);
+        let total = Duration::<Hours>::new(1.) + Duration::<Minutes>::new(30.).try_into().unwrap();
+        assert_eq!(total.to::<Minutes>().unwrap().value(), 90.);
+        assert_eq!(
+            Information::<Kibibytes>::new(2.)
+                .to::<Bytes>()
+                .unwrap()
+                .value(),
+            2048.
+        );
+        assert_eq!(Length::<Feet>::new(3.) * 2., Length::new(6.));
+        assert_eq!(Mass::<Grams>::new(250.).to_string(), "250 g");
+    }
+
+    #[test]
+    fn every_unit_is_a_standard_unit_of_its_dimension() {
+        let table = standard_table();
+        for symbols in [
+            Length::<Meters>::SYMBOLS,
+            Duration::<Seconds>::SYMBOLS,
+            Mass::<Grams>::SYMBOLS,
+            Information::<Bytes>::SYMBOLS,
+            Volume::<Liters>::SYMBOLS,
+        ] {
+            let dimensions: Vec<_> = symbols
+                .iter()
+                .map(|symbol| table.dimension(table.id(symbol).expect(symbol)))
+                .collect();
+            assert!(
+                dimensions.windows(2).all(|pair| pair[0] == pair[1]),
+                "{:?} measure different dimensions",
+                symbols
+            );
+        }
+    }
+
+    #[test]
+    fn reports_units_the_standard_facts_lack() {
+        crate::units! {
+            Odd(OddUnit) { Meters = "m", Furlongs = "fur", Seconds = "s" }
+        }
+        assert!(matches!(
+            Odd::<Meters>::new(1.).to::<Furlongs>(),
+            Err(ConversionError::UnknownUnit { .. })
+        ));
+        assert!(matches!(
+            Odd::<Meters>::new(1.).to::<Seconds>(),
+            Err(ConversionError::IncompatibleDimensions { .. })
+        ));
+        assert!(Quantity::try_from(Odd::<Furlongs>::new(1.)).is_err());
+    }
+
+    #[test]
+    fn bridges_to_quantities() {
+        let quantity = Quantity::try_from(Length::<Miles>::new(1.)).unwrap();
+        assert_eq!(quantity.to_string(), "1 mi");
+        let feet = Length::<Feet>::try_from(quantity).unwrap();
+        assert_eq!(feet.value(), 5280.);
+        let err = Duration::<Seconds>::try_from(Quantity::new(1., "m").unwrap()).unwrap_err();
+        assert!(matches!(
+            err,
+            ConversionError::IncompatibleDimensions { .. }
+        ));
+    }
+}
@@ -0,0 +1,214 @@
+use super::number::{Number, Scalar};
+use super::unit_conversion::ConversionError;
+use std::fmt;
+use std::ops::{Add, Div, Mul, Sub};
+
+/// A value with a standard uncertainty, e.g. `3.28 ± 0.001`.
+///
+/// Arithmetic propagates the uncertainty to first order, treating the two
+/// sides as independent: `(a ± σa) * (b ± σb)` is
+/// `ab ± √((b σa)² + (a σb)²)`. Converting an `Uncertain` value carries the
+/// uncertainty of the value and of every fact along the path into the result.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Uncertain {
+    pub value: f64,
+    pub sigma: f64,
+}
+
+impl Uncertain {
+    pub fn new(value: f64, sigma: f64) -> Self {
+        Self { value, sigma }
+    }
+
+    /// The uncertainty rounded up to one significant figure, e.g. `0.03` for
+    /// 0.024, or `None` if there is none.
+    pub fn format_sigma(&self) -> Option<String> {
+        let (digit, place) = self.rounded_sigma()?;
+        Some(match place {
+            ..0 => format!("{:.*}", (-place) as usize, digit * 10f64.powi(place)),
+            _ => (digit * 10f64.powi(place)).to_string(),
+        })
+    }
+
+    /// The value rounded to the place of its uncertainty and the uncertainty
+    /// rounded up to one significant figure, the numbers the `Display` impl
+    /// writes.
+    pub fn rounded(&self) -> Self {
+        let Some((digit, place)) = self.rounded_sigma() else {
+            return *self;
+        };
+        let value = match place {
+            ..0 => format!("{:.*}", (-place) as usize, self.value)
+                .parse()
+                .unwrap_or(self.value),
+            _ => {
+                let step = 10f64.powi(place);
+                (self.value / step).round() * step
+            }
+        };
+        let sigma = self
+            .format_sigma()
+            .and_then(|sigma| sigma.parse().ok())
+            .unwrap_or(digit * 10f64.powi(place));
+        Self::new(value, sigma)
+    }
+
+    // The leading digit of the rounded uncertainty and its decimal place.
+    fn rounded_sigma(&self) -> Option<(f64, i32)> {
+        if self.sigma == 0. || !self.sigma.is_finite() {
+            return None;
+        }
+        let place = self.sigma.log10().floor() as i32;
+        // Rounding up can carry into the next place, as in 0.096 -> 0.1.
+        match (self.sigma / 10f64.powi(place) * (1. - 1e-12)).ceil() {
+            digit if digit >= 10. => Some((1., place + 1)),
+            digit => Some((digit, place)),
+        }
+    }
+}
+
+impl Add for Uncertain {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self::new(self.value + other.value, self.sigma.hypot(other.sigma))
+    }
+}
+
+impl Sub for Uncertain {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        Self::new(self.value - other.value, self.sigma.hypot(other.sigma))
+    }
+}
+
+impl Mul for Uncertain {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        let sigma = (other.value * self.sigma).hypot(self.value * other.sigma);
+        Self::new(self.value * other.value, sigma)
+    }
+}
+
+impl Div for Uncertain {
+    type Output = Self;
+
+    fn div(self, other: Self) -> Self {
+        let value = self.value / other.value;
+        let sigma = (self.sigma / other.value).hypot(value * other.sigma / other.value);
+        Self::new(value, sigma)
+    }
+}
+
+impl Scalar for Uncertain {
+    fn one() -> Self {
+        Self::new(1., 0.)
+    }
+
+    fn of(number: &Number) -> Self {
+        Self::new(number.value(), 0.)
+    }
+
+    fn measured(number: &Number, relative_uncertainty: f64) -> Self {
+        Self::new(
+            number.value(),
+            (number.value() * relative_uncertainty).abs(),
+        )
+    }
+
+    fn powi(self, exponent: i32) -> Self {
+        let value = self.value.powi(exponent);
+        let sigma = (exponent as f64 * self.value.powi(exponent - 1) * self.sigma).abs();
+        Self::new(value, sigma)
+    }
+}
+
+/// Writes the uncertainty rounded up to one significant figure and the value
+/// rounded to the same place, e.g. `78.72 ± 0.03`. Without an uncertainty the
+/// value is written as is.
+impl fmt::Display for Uncertain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (Some((_, place)), Some(sigma)) = (self.rounded_sigma(), self.format_sigma()) else {
+            return write!(f, "{}", self.value);
+        };
+        match place {
+            ..0 => write!(f, "{:.*} ± {}", (-place) as usize, self.value, sigma),
+            _ => {
+                let step = 10f64.powi(place);
+                write!(f, "{} ± {}", (self.value / step).round() * step, sigma)
+            }
+        }
+    }
+}
+
+/// Splits a measured value such as `3.28 ± 0.001`, `3.28±0.001` or
+/// `3.28 +/- 0.001` into the value and its standard uncertainty, which is 0
+/// when none is given.
+pub fn parse_measured(s: &str) -> Result<(Number, f64), ConversionError> {
+    let (value, uncertainty) = match split_uncertainty(s) {
+        Some((value, uncertainty)) => (value, Some(uncertainty)),
+        None => (s, None),
+    };
+    let value: Number = value.parse()?;
+    let uncertainty = match uncertainty {
+        None => 0.,
+        Some(uncertainty) => {
+            parse_uncertainty(uncertainty).ok_or_else(|| ConversionError::ParseError {
+                input: s.to_string(),
+                position: s.len() - uncertainty.trim_start().len(),
+                reason: "expected a non-negative uncertainty after `±`".to_string(),
+            })?
+        }
+    };
+    Ok((value, uncertainty))
+}
+
+/// Splits `s` around its first `±` (or `+/-`), if it has one.
+pub fn split_uncertainty(s: &str) -> Option<(&str, &str)> {
+    ["±", "+/-"]
+        .into_iter()
+        .filter_map(|sign| s.find(sign).map(|i| (i, sign.len())))
+        .min()
+        .map(|(i, len)| (&s[..i], &s[i + len..]))
+}
+
+/// Reads a standard uncertainty: a finite number no less than 0.
+pub fn parse_uncertainty(s: &str) -> Option<f64> {
+    s.trim()
+        .parse::<Number>()
+        .ok()
+        .map(|number| number.value())
+        .filter(|sigma| sigma.is_finite() && *sigma >= 0.)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn propagates_to_first_order() {
+        let length = Uncertain::new(2., 0.) * Uncertain::new(3.28, 0.001) * Uncertain::new(12., 0.);
+        assert!((length.value - 78.72).abs() < 1e-12);
+        assert!((length.sigma - 0.024).abs() < 1e-12);
+        assert_eq!(length.to_string(), "78.72 ± 0.03");
+
+        let ratio = Uncertain::new(10., 0.3) / Uncertain::new(2., 0.04);
+        assert!((ratio.sigma - 5. * (0.03f64).hypot(0.02)).abs() < 1e-12);
+        let area = Uncertain::new(3., 0.1).powi(2);
+        assert!((area.sigma - 0.6).abs() < 1e-12);
+    }
+
+    #[test]
+    fn writes_and_reads_measured_values() {
+        assert_eq!(Uncertain::new(78.72, 0.).to_string(), "78.72");
+        assert_eq!(Uncertain::new(1.2345, 0.096).to_string(), "1.2 ± 0.1");
+        assert_eq!(Uncertain::new(1234.5, 23.).to_string(), "1230 ± 30");
+        let (value, sigma) = parse_measured("3.28 ± 0.001").unwrap();
+        assert_eq!((value, sigma), (Number::from(3.28), 0.001));
+        assert_eq!(parse_measured("3.28+/-0.001").unwrap().1, 0.001);
+        assert_eq!(parse_measured("3.28").unwrap().1, 0.);
+        assert!(parse_measured("3.28 ± -1").is_err());
+    }
+}
@@ -1,15 +1,48 @@
 mod cli;
-mod conversion;
 mod dice_game;
 mod expected_values;
 mod game;
+mod repl;
 
-use crate::conversion::unit_conversion;
-use cli::parse_args;
+use cli::{parse_args, Amount};
+use jane_street_mock_interview::conversion::consistency::ConsistencyCheck;
+use jane_street_mock_interview::conversion::diagram;
+use jane_street_mock_interview::conversion::graph::{GraphSource, UnitGraph};
+use jane_street_mock_interview::conversion::mixed;
+use jane_street_mock_interview::conversion::quantity::Quantity;
+use jane_street_mock_interview::conversion::query;
+use jane_street_mock_interview::conversion::registry::{self, RegistryFormat};
+use jane_street_mock_interview::conversion::server::{Server, Service};
+use jane_street_mock_interview::conversion::session::Session;
+use jane_street_mock_interview::conversion::table;
+use jane_street_mock_interview::conversion::unit_conversion::{self, ConversionError};
 use std::error::Error;
+use std::fs;
 use tracing::{info, level_filters::LevelFilter};
 fn main() -> Result<(), Box<dyn Error>> {
-    let (num_sims, output, verbosity, unit_conversion) = parse_args();
+    let cli::Args {
+        num_sims,
+        output,
+        verbosity,
+        unit_conversion,
+        best,
+        breakdown,
+        facts,
+        rates,
+        as_of,
+        tolerance,
+        strict,
+        exact,
+        explain,
+        route,
+        format,
+        query,
+        queries,
+        repl,
+        convert_table,
+        export_graph,
+        serve,
+    } = parse_args();
 
     setup_logging(verbosity);
     info!("verbosity level: {:?}", verbosity);
@@ -18,17 +51,169 @@ fn main() -> Result<(), Box<dyn Error>> {
         let output_fp = dice_game::simulate_dice_games(num_sims, output.as_deref())?;
         println!("Output written to file: {}", output_fp);
     }
-    if let Some((value, from, to)) = unit_conversion {
-        let result = unit_conversion::ConversionQuery::convert(value, &from, &to);
+    if unit_conversion.is_none()
+        && query.is_none()
+        && queries.is_none()
+        && !repl
+        && convert_table.is_none()
+        && export_graph.is_none()
+        && serve.is_none()
+    {
+        return Ok(());
+    }
+    let mut check = ConsistencyCheck {
+        strict,
+        ..ConsistencyCheck::default()
+    };
+    if let Some(tolerance) = tolerance {
+        check.tolerance = tolerance;
+    }
+    let source = GraphSource {
+        facts,
+        rates,
+        as_of,
+    };
+    let graph = match load_graph(&source, &check) {
+        Ok(graph) => graph,
+        Err(e) => {
+            eprintln!("Facts error: {}", e);
+            return Ok(());
+        }
+    };
+    if let Some((amount, to)) = unit_conversion {
+        let (value, uncertainty, from) = match amount {
+            Amount::Measured {
+                value,
+                uncertainty,
+                unit,
+            } => (value, uncertainty, unit),
+            Amount::Mixed(mixed) => match mixed::parse_mixed(&graph, &mixed) {
+                Ok((value, unit)) => (value, 0., unit.to_string()),
+                Err(e) => {
+                    eprintln!("Conversion error: {}", e);
+                    return Ok(());
+                }
+            },
+        };
+        let result = match (to, best, breakdown) {
+            (_, Some(system), _) => Quantity::with_graph(&graph, value.value(), &from)
+                .and_then(|quantity| quantity.to_best(&system))
+                .map(|best| format.quantity(&graph, best.value(), best.unit())),
+            (_, _, Some(units)) => graph.parse_unit(&from).and_then(|from| {
+                let parts = units
+                    .iter()
+                    .map(|unit| graph.parse_unit(unit))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(format.mixed(&graph, &graph.breakdown(&value, &from, &parts)?))
+            }),
+            (Some(to), None, None) => unit_conversion::ConversionQuery::with_graph(
+                &graph, value, &from, &to,
+            )
+            .and_then(|query| {
+                let query = query.with_uncertainty(uncertainty).with_route(route);
+                let converted = query.convert_uncertain()?;
+                let exact = match exact {
+                    true => Some(query.convert_units_exact()?),
+                    false => None,
+                };
+                let mut text = format!(
+                    "{} {}",
+                    query::format_answer(&format, &converted, exact.as_ref()),
+                    format.unit(&graph, query.to(), converted.value)
+                );
+                if explain {
+                    text = format!("{}\n{}", text, query.explain()?);
+                }
+                Ok(text)
+            }),
+            (None, None, None) => {
+                eprintln!("Conversion error: missing the unit to convert to");
+                return Ok(());
+            }
+        };
         match result {
             Ok(converted_value) => println!("Converted value: {}", converted_value),
             Err(e) => eprintln!("Conversion error: {}", e),
         }
     }
+    let options = query::AnswerOptions {
+        exact,
+        explain,
+        format,
+        route,
+    };
+    if let Some(line) = query {
+        println!("{}", query::answer_line(&graph, &line, options));
+    }
+    if let Some(path) = queries {
+        for answer in query::answer_batch(&graph, &fs::read_to_string(path)?, options) {
+            println!("{}", answer);
+        }
+    }
+    if let Some((input, system, out)) = convert_table {
+        let source = fs::read_to_string(&input)?;
+        match table::convert_table(&graph, &source, Some(&input), &system, &format) {
+            Ok(table) => {
+                for failure in &table.failures {
+                    eprintln!("Row error: {}", failure);
+                }
+                match out {
+                    Some(path) => fs::write(path, &table.csv)?,
+                    None => print!("{}", table.csv),
+                }
+                eprintln!(
+                    "Converted {} of {} rows",
+                    table.converted,
+                    table.converted + table.failures.len()
+                );
+            }
+            Err(e) => eprintln!("Conversion error: {}", e),
+        }
+    }
+    if let Some((format, out)) = export_graph {
+        let registry = graph.registry();
+        let exported = match format.as_str() {
+            "mermaid" => diagram::mermaid(&registry),
+            "dot" => diagram::dot(&registry),
+            name => registry::to_string(
+                &registry,
+                RegistryFormat::from_name(name).expect("clap only allows known formats"),
+            ),
+        };
+        match out {
+            Some(path) => fs::write(path, exported)?,
+            None => print!("{}", exported),
+        }
+    }
+    if repl {
+        repl::run(Session::new(graph, options, check))?;
+    } else if let Some(port) = serve {
+        let service = Service::new(graph, source, check);
+        match Server::bind(&format!("127.0.0.1:{}", port), service) {
+            Ok(server) => {
+                if let Some(address) = server.address() {
+                    eprintln!("Serving conversions on http://{}", address);
+                }
+                server.run();
+            }
+            Err(e) => eprintln!("Server error: {}", e),
+        }
+    }
 
     Ok(())
 }
 
+fn load_graph(
+    source: &GraphSource,
+    check: &ConsistencyCheck,
+) -> Result<UnitGraph, ConversionError> {
+    let graph = source.load()?;
+    for inconsistency in check.verify(&graph)? {
+        eprintln!("Facts warning: {}", inconsistency);
+    }
+    Ok(graph)
+}
+
 fn setup_logging(verbosity: u8) {
     let tracing_level = match verbosity {
         0 => LevelFilter::OFF,
@@ -0,0 +1,79 @@
+use jane_street_mock_interview::conversion::session::Session;
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+use std::env;
+use std::path::PathBuf;
+
+const PROMPT: &str = "> ";
+const HISTORY_FILE: &str = ".unit_conversion_history";
+
+// Owns the session so tab completion sees units defined during it.
+struct ReplHelper {
+    session: Session,
+}
+
+impl Completer for ReplHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        Ok(self.session.complete(line, pos))
+    }
+}
+
+impl Hinter for ReplHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ReplHelper {}
+
+impl Validator for ReplHelper {}
+
+impl Helper for ReplHelper {}
+
+/// Answers queries and definitions typed at a prompt until `quit` or Ctrl-D,
+/// keeping the history in `~/.unit_conversion_history`.
+pub fn run(session: Session) -> rustyline::Result<()> {
+    let mut editor: Editor<ReplHelper, DefaultHistory> = Editor::new()?;
+    editor.set_helper(Some(ReplHelper { session }));
+    let history = history_path();
+    // There is no history the first time.
+    let _ = editor.load_history(&history);
+    println!("Type `help` for the commands, `quit` to leave.");
+
+    loop {
+        let line = match editor.readline(PROMPT) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(e),
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        editor.add_history_entry(line)?;
+        if matches!(line, "quit" | "exit") {
+            break;
+        }
+        let helper = editor.helper_mut().expect("the helper was set above");
+        println!("{}", helper.session.eval(line));
+    }
+    editor.save_history(&history)
+}
+
+fn history_path() -> PathBuf {
+    env::var_os("HOME")
+        .map(PathBuf::from)
+        .unwrap_or_default()
+        .join(HISTORY_FILE)
+}